@@ -0,0 +1,265 @@
+(() => {
+    "use strict";
+
+    const {
+        $,
+        AuthError,
+        LOGIN_URL,
+        api,
+        resolve,
+        formatDuration,
+        formatTime,
+        formatNumber,
+        drawChart,
+        cell,
+        linkCell,
+        fillTable,
+    } = window.Dashboard;
+    const MAX_POINTS = 300;
+
+    const state = {
+        rangeSecs: 900,
+        series: new Map(),
+        latest: null,
+        source: null,
+        sort: { key: "cpu_pct", dir: "desc" },
+    };
+
+    const charts = {
+        cpu: {
+            canvas: "chart-cpu",
+            max: 100,
+            metrics: () => [["cpu_usage_pct", "CPU"]],
+        },
+        cores: {
+            canvas: "chart-cores",
+            max: 100,
+            metrics: (s) => s.cpu_per_core_usage_pct.map((_, i) => [`cpu_per_core_usage_pct[${i}]`, `core ${i}`]),
+        },
+        memory: {
+            canvas: "chart-memory",
+            metrics: () => [["mem_used_mb", "memory"], ["swap_used_mb", "swap"]],
+        },
+        disks: {
+            canvas: "chart-disks",
+            max: 100,
+            metrics: (s) => s.disks.map((d) => [`disks[${d.mount_point}].used_pct`, d.mount_point]),
+        },
+        network: {
+            canvas: "chart-network",
+            metrics: (s) =>
+                s.network.flatMap((n) => [
+                    [`network[${n.name}].received_kbps`, `${n.name} ↓`],
+                    [`network[${n.name}].transmitted_kbps`, `${n.name} ↑`],
+                ]),
+        },
+    };
+
+    // --- Charts ----------------------------------------------------------
+
+    function chartLines(chart) {
+        if (!state.latest) return [];
+        return chart.metrics(state.latest).map(([path, label]) => ({
+            label,
+            points: state.series.get(path) || [],
+        }));
+    }
+
+    function renderCharts() {
+        for (const chart of Object.values(charts)) {
+            drawChart($(chart.canvas), chartLines(chart), { max: chart.max });
+        }
+        drawChart($("cpu-spark"), [{ label: "cpu", points: state.series.get("cpu_usage_pct") || [] }], { max: 100, axes: false });
+        drawChart($("mem-spark"), [{ label: "mem", points: state.series.get("mem_used_mb") || [] }], {
+            max: state.latest?.mem_total_mb || undefined,
+            axes: false,
+        });
+        drawChart($("swap-spark"), [{ label: "swap", points: state.series.get("swap_used_mb") || [] }], {
+            max: state.latest?.swap_total_mb || undefined,
+            axes: false,
+        });
+    }
+
+    function trackedPaths() {
+        if (!state.latest) return [];
+        return Object.values(charts).flatMap((chart) => chart.metrics(state.latest).map(([path]) => path));
+    }
+
+    async function loadRange() {
+        const to = Date.now();
+        const from = to - state.rangeSecs * 1000;
+        const step = `${Math.max(1, Math.round(state.rangeSecs / MAX_POINTS))}s`;
+        const paths = trackedPaths();
+
+        const results = await Promise.all(
+            paths.map((metric) => api("/api/v1/series", { metric, from, to, step, agg: "avg" }).catch(() => null)),
+        );
+
+        state.series = new Map();
+        paths.forEach((path, i) => state.series.set(path, results[i]?.points || []));
+        renderCharts();
+    }
+
+    function appendSnapshot(snapshot) {
+        const cutoff = snapshot.timestamp - state.rangeSecs * 1000;
+        for (const path of trackedPaths()) {
+            const value = resolve(snapshot, path);
+            const points = state.series.get(path) || [];
+            if (value !== null) points.push([snapshot.timestamp, value]);
+            while (points.length && points[0][0] < cutoff) points.shift();
+            state.series.set(path, points);
+        }
+    }
+
+    // --- Tables & cards --------------------------------------------------
+
+    function renderProcesses() {
+        if (!state.latest) return;
+        const { key, dir } = state.sort;
+        const processes = [...state.latest.top_processes].sort((a, b) => {
+            const left = a[key] ?? "";
+            const right = b[key] ?? "";
+            const order = typeof left === "string" ? left.localeCompare(right) : left - right;
+            return dir === "asc" ? order : -order;
+        });
+
+        fillTable(
+            "process-table",
+            processes.map((p) => [
+                cell(p.pid, true),
+                linkCell(p.name, `/ui/processes/${p.pid}`),
+                cell(formatNumber(p.cpu_pct), true),
+                cell(p.memory_mb, true),
+                cell(formatNumber(p.memory_pct), true),
+                cell(formatNumber(p.disk_read_kbps), true),
+                cell(formatNumber(p.disk_write_kbps), true),
+                cell(p.thread_count ?? "–", true),
+                cell(p.status ?? "–"),
+            ]),
+        );
+
+        $("process-table")
+            .querySelectorAll("th")
+            .forEach((th) => {
+                th.classList.toggle("asc", th.dataset.key === key && dir === "asc");
+                th.classList.toggle("desc", th.dataset.key === key && dir === "desc");
+            });
+    }
+
+    function renderSnapshot(snapshot) {
+        state.latest = snapshot;
+        const host = snapshot.hostname || "Agent Monitor";
+        $("hostname").textContent = host;
+        document.title = host;
+
+        $("cpu-value").textContent = `${formatNumber(snapshot.cpu_usage_pct)}%`;
+        $("cpu-load").textContent = `Load ${[snapshot.load_avg_one, snapshot.load_avg_five, snapshot.load_avg_fifteen]
+            .map((v) => formatNumber(v, 2))
+            .join(" ")}`;
+        $("mem-value").textContent = `${snapshot.mem_used_mb}/${snapshot.mem_total_mb} MB`;
+        $("mem-detail").textContent = `Available ${snapshot.mem_available_mb} MB`;
+        $("swap-value").textContent = `${snapshot.swap_used_mb}/${snapshot.swap_total_mb} MB`;
+        $("swap-detail").textContent = `Free ${snapshot.swap_free_mb} MB`;
+        $("uptime-value").textContent = formatDuration(snapshot.uptime_seconds);
+        $("updated").textContent = `Last refreshed ${formatTime(snapshot.timestamp, true)}`;
+
+        fillTable(
+            "disk-table",
+            snapshot.disks.map((d) => [
+                cell(d.mount_point),
+                cell(d.name),
+                cell(d.filesystem),
+                cell(`${formatNumber(d.used_gb)} GB`, true),
+                cell(`${formatNumber(d.total_gb)} GB`, true),
+                cell(formatNumber(d.used_pct), true),
+            ]),
+        );
+
+        $("gpu-section").hidden = snapshot.gpus.length === 0;
+        fillTable(
+            "gpu-table",
+            snapshot.gpus.map((g) => [
+                cell(g.index, true),
+                cell(g.name),
+                cell(formatNumber(g.gpu_usage_pct), true),
+                cell(`${g.memory_used_mb ?? "–"} / ${g.memory_total_mb ?? "–"} MB`, true),
+                cell(g.temperature_celsius === null ? "–" : `${formatNumber(g.temperature_celsius, 0)} °C`, true),
+            ]),
+        );
+
+        renderProcesses();
+    }
+
+    // --- Live updates ----------------------------------------------------
+
+    function setStatus(live) {
+        const status = $("status");
+        status.textContent = live ? "live" : "offline";
+        status.className = `status ${live ? "live" : "offline"}`;
+    }
+
+    function connectStream() {
+        state.source?.close();
+        const source = new EventSource("/api/v1/stream");
+        source.onopen = () => setStatus(true);
+        source.onerror = () => {
+            setStatus(false);
+            api("/api/v1/system").catch((err) => {
+                if (err instanceof AuthError) redirectToLogin();
+            });
+        };
+        source.addEventListener("snapshot", (event) => {
+            const snapshot = JSON.parse(event.data);
+            renderSnapshot(snapshot);
+            appendSnapshot(snapshot);
+            renderCharts();
+        });
+        state.source = source;
+    }
+
+    // --- Startup ---------------------------------------------------------
+
+    function redirectToLogin() {
+        state.source?.close();
+        window.location.assign(LOGIN_URL);
+    }
+
+    async function start() {
+        try {
+            renderSnapshot(await api("/api/v1/system"));
+        } catch (err) {
+            if (err instanceof AuthError) {
+                redirectToLogin();
+            } else {
+                setStatus(false);
+            }
+            return;
+        }
+        await loadRange();
+        connectStream();
+    }
+
+    function init() {
+        $("range").addEventListener("change", (event) => {
+            state.rangeSecs = Number(event.target.value);
+            loadRange();
+        });
+
+        $("process-table")
+            .querySelectorAll("th")
+            .forEach((th) =>
+                th.addEventListener("click", () => {
+                    const key = th.dataset.key;
+                    const dir = state.sort.key === key && state.sort.dir === "desc" ? "asc" : "desc";
+                    state.sort = { key, dir };
+                    renderProcesses();
+                }),
+            );
+
+        window.addEventListener("resize", renderCharts);
+
+        start();
+    }
+
+    init();
+})();
@@ -0,0 +1,204 @@
+// Helpers shared by the dashboard pages (`app.js`, `process.js`).
+window.Dashboard = (() => {
+    "use strict";
+
+    const LOGIN_URL = "/ui/login";
+    const IDENTITY_FIELDS = ["mount_point", "name", "index", "pid"];
+    const COLORS = ["#60a5fa", "#f472b6", "#34d399", "#fbbf24", "#a78bfa", "#f87171", "#22d3ee", "#a3e635"];
+
+    class AuthError extends Error {}
+
+    const $ = (id) => document.getElementById(id);
+
+    // --- API -------------------------------------------------------------
+
+    async function api(path, params = {}) {
+        const url = new URL(path, window.location.origin);
+        Object.entries(params).forEach(([name, value]) => {
+            if (value !== undefined && value !== null) {
+                url.searchParams.set(name, value);
+            }
+        });
+        // The session cookie set by the login page authenticates every request.
+        const response = await fetch(url, { credentials: "same-origin" });
+        if (response.status === 401) {
+            throw new AuthError("unauthorized");
+        }
+        if (!response.ok) {
+            // Errors come as {"error": {"code", "message"}}.
+            const body = await response.json().catch(() => null);
+            throw new Error(`${path}: ${body?.error?.message ?? `HTTP ${response.status}`}`);
+        }
+        return response.json();
+    }
+
+    // Mirrors the metric path resolution of the agent (`series.rs`).
+    function resolve(snapshot, path) {
+        let current = snapshot;
+        for (const segment of path.match(/[^.[\]]+(\[[^\]]+\])?/g) || []) {
+            const [, field, selector] = segment.match(/^([^[]+)(?:\[([^\]]+)\])?$/);
+            current = current?.[field];
+            if (selector !== undefined) {
+                if (!Array.isArray(current)) {
+                    return null;
+                }
+                const byIdentity = current.find(
+                    (item) => item && typeof item === "object" && IDENTITY_FIELDS.some((key) => String(item[key]) === selector),
+                );
+                current = byIdentity ?? current[Number(selector)];
+            }
+        }
+        return typeof current === "number" ? current : null;
+    }
+
+    // --- Formatting ------------------------------------------------------
+
+    function formatDuration(totalSeconds) {
+        const days = Math.floor(totalSeconds / 86400);
+        const hours = Math.floor((totalSeconds % 86400) / 3600);
+        const minutes = Math.floor((totalSeconds % 3600) / 60);
+        const seconds = totalSeconds % 60;
+        if (days > 0) return `${days}d ${hours}h ${minutes}m`;
+        if (hours > 0) return `${hours}h ${minutes}m`;
+        if (minutes > 0) return `${minutes}m ${seconds}s`;
+        return `${seconds}s`;
+    }
+
+    function formatTime(ms, withDate = false) {
+        const date = new Date(ms);
+        return withDate ? date.toLocaleString() : date.toLocaleTimeString();
+    }
+
+    function formatNumber(value, digits = 1) {
+        if (value === null || value === undefined) return "–";
+        return Number(value).toFixed(digits);
+    }
+
+    function formatAxis(value) {
+        if (Math.abs(value) >= 10000) return `${(value / 1000).toFixed(0)}k`;
+        if (Math.abs(value) >= 100) return value.toFixed(0);
+        return value.toFixed(1);
+    }
+
+    // --- Charts ----------------------------------------------------------
+
+    function drawChart(canvas, lines, { max, axes = true } = {}) {
+        const dpr = window.devicePixelRatio || 1;
+        const width = canvas.clientWidth;
+        const height = canvas.clientHeight;
+        canvas.width = width * dpr;
+        canvas.height = height * dpr;
+        const ctx = canvas.getContext("2d");
+        ctx.scale(dpr, dpr);
+        ctx.clearRect(0, 0, width, height);
+        ctx.font = "11px Arial, sans-serif";
+
+        let tMin = Infinity;
+        let tMax = -Infinity;
+        let vMax = 0;
+        for (const line of lines) {
+            for (const [ts, value] of line.points) {
+                tMin = Math.min(tMin, ts);
+                tMax = Math.max(tMax, ts);
+                vMax = Math.max(vMax, value);
+            }
+        }
+
+        if (!Number.isFinite(tMin)) {
+            if (axes) {
+                ctx.fillStyle = "#6b7280";
+                ctx.fillText("No data", width / 2 - 20, height / 2);
+            }
+            return;
+        }
+
+        vMax = max ?? (vMax > 0 ? vMax * 1.1 : 1);
+        const pad = axes ? { left: 44, right: 8, top: 8, bottom: 18 } : { left: 0, right: 0, top: 2, bottom: 2 };
+        const plotWidth = width - pad.left - pad.right;
+        const plotHeight = height - pad.top - pad.bottom;
+        const x = (ts) => pad.left + (tMax === tMin ? plotWidth : ((ts - tMin) / (tMax - tMin)) * plotWidth);
+        const y = (value) => pad.top + plotHeight - (Math.min(value, vMax) / vMax) * plotHeight;
+
+        if (axes) {
+            ctx.strokeStyle = "#374151";
+            ctx.fillStyle = "#9ca3af";
+            for (let i = 0; i <= 4; i++) {
+                const value = (vMax / 4) * i;
+                const gy = y(value);
+                ctx.beginPath();
+                ctx.moveTo(pad.left, gy);
+                ctx.lineTo(width - pad.right, gy);
+                ctx.stroke();
+                ctx.fillText(formatAxis(value), 4, gy + 4);
+            }
+            ctx.fillText(formatTime(tMin), pad.left, height - 4);
+            const endLabel = formatTime(tMax);
+            ctx.fillText(endLabel, width - pad.right - ctx.measureText(endLabel).width, height - 4);
+        }
+
+        lines.forEach((line, index) => {
+            if (!line.points.length) return;
+            ctx.strokeStyle = COLORS[index % COLORS.length];
+            ctx.lineWidth = axes ? 1.5 : 1.2;
+            ctx.beginPath();
+            line.points.forEach(([ts, value], i) => {
+                if (i === 0) ctx.moveTo(x(ts), y(value));
+                else ctx.lineTo(x(ts), y(value));
+            });
+            ctx.stroke();
+        });
+
+        if (axes && lines.length > 1) {
+            let lx = pad.left + 6;
+            lines.slice(0, 8).forEach((line, index) => {
+                ctx.fillStyle = COLORS[index % COLORS.length];
+                ctx.fillText(line.label, lx, pad.top + 10);
+                lx += ctx.measureText(line.label).width + 12;
+            });
+        }
+    }
+
+    // --- Tables ----------------------------------------------------------
+
+    function cell(text, numeric = false) {
+        const td = document.createElement("td");
+        td.textContent = text;
+        if (numeric) td.className = "num";
+        return td;
+    }
+
+    function linkCell(text, href) {
+        const td = document.createElement("td");
+        const a = document.createElement("a");
+        a.href = href;
+        a.textContent = text;
+        td.append(a);
+        return td;
+    }
+
+    function fillTable(id, rows) {
+        const body = $(id).querySelector("tbody");
+        body.replaceChildren(
+            ...rows.map((cells) => {
+                const tr = document.createElement("tr");
+                tr.append(...cells);
+                return tr;
+            }),
+        );
+    }
+
+    return {
+        $,
+        AuthError,
+        LOGIN_URL,
+        api,
+        resolve,
+        formatDuration,
+        formatTime,
+        formatNumber,
+        drawChart,
+        cell,
+        linkCell,
+        fillTable,
+    };
+})();
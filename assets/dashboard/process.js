@@ -0,0 +1,115 @@
+(() => {
+    "use strict";
+
+    const { $, AuthError, LOGIN_URL, api, formatDuration, formatTime, formatNumber, drawChart, cell, linkCell, fillTable } =
+        window.Dashboard;
+    const REFRESH_MS = 15000;
+
+    // The page is served at /ui/processes/{pid}.
+    const pid = Number(window.location.pathname.split("/").pop());
+
+    const state = {
+        rangeSecs: 3600,
+        process: null,
+        timer: null,
+    };
+
+    const charts = {
+        "chart-cpu": (h) => [{ label: "cpu", points: h.map((p) => [p.timestamp, p.cpu_pct]) }],
+        "chart-memory": (h) => [{ label: "rss", points: h.map((p) => [p.timestamp, p.memory_mb]) }],
+        "chart-io": (h) => [
+            { label: "read", points: h.map((p) => [p.timestamp, p.disk_read_kbps]) },
+            { label: "write", points: h.map((p) => [p.timestamp, p.disk_write_kbps]) },
+        ],
+        "chart-threads": (h) => [
+            { label: "threads", points: h.filter((p) => p.thread_count !== null).map((p) => [p.timestamp, p.thread_count]) },
+        ],
+    };
+
+    const processLink = (ref) => (ref ? linkCell(`${ref.name} (${ref.pid})`, `/ui/processes/${ref.pid}`) : cell("–"));
+
+    function renderCharts() {
+        const history = state.process?.history || [];
+        for (const [id, lines] of Object.entries(charts)) {
+            drawChart($(id), lines(history));
+        }
+    }
+
+    function renderProcess(process) {
+        state.process = process;
+        const details = process.details;
+        const current = process.current ?? process.history[process.history.length - 1] ?? null;
+        const name = details?.name ?? process.current?.name ?? `PID ${pid}`;
+
+        $("title").textContent = `${name} (${pid})`;
+        document.title = `${name} (${pid})`;
+        $("status").textContent = process.running ? "running" : "not running";
+        $("status").className = `status ${process.running ? "live" : "offline"}`;
+
+        $("cpu-value").textContent = current ? `${formatNumber(current.cpu_pct)}%` : "–";
+        $("threads").textContent = `Threads ${current?.thread_count ?? "–"}`;
+        $("mem-value").textContent = current ? `${current.memory_mb} MB` : "–";
+        $("mem-detail").textContent = process.current
+            ? `${formatNumber(process.current.memory_pct)}% · virtual ${process.current.virtual_memory_mb} MB`
+            : "–";
+        $("io-value").textContent = current
+            ? `${formatNumber(current.disk_read_kbps)} / ${formatNumber(current.disk_write_kbps)}`
+            : "–";
+        $("runtime-value").textContent = details ? formatDuration(details.run_time_seconds) : "–";
+        $("started").textContent = details ? `Started ${formatTime(details.started_at, true)}` : "–";
+
+        const row = (label, value) => [cell(label), value instanceof Node ? value : cell(value ?? "–")];
+        fillTable("detail-table", [
+            row("Command line", details?.command.length ? details.command.join(" ") : null),
+            row("Executable", details?.executable),
+            row("Working directory", details?.working_directory),
+            row("Parent", processLink(details?.parent)),
+            row("Status", process.current?.status),
+            row("Last seen in top list", process.last_seen ? formatTime(process.last_seen, true) : null),
+        ]);
+
+        fillTable(
+            "children-table",
+            (details?.children || []).map((child) => [cell(child.pid, true), linkCell(child.name, `/ui/processes/${child.pid}`)]),
+        );
+
+        fillTable(
+            "presence-table",
+            [...process.top_list].reverse().map((span) => [
+                cell(formatTime(span.entered, true)),
+                cell(span.left ? formatTime(span.left, true) : "still listed"),
+                cell(formatDuration(Math.round(((span.left ?? Date.now()) - span.entered) / 1000)), true),
+            ]),
+        );
+
+        renderCharts();
+    }
+
+    async function refresh() {
+        const to = Date.now();
+        const from = to - state.rangeSecs * 1000;
+        try {
+            renderProcess(await api(`/api/v1/processes/${pid}`, { from, to }));
+            $("error").textContent = "";
+        } catch (err) {
+            if (err instanceof AuthError) {
+                window.location.assign(LOGIN_URL);
+                return;
+            }
+            $("error").textContent = `Process ${pid} is not known to the agent.`;
+        }
+    }
+
+    function init() {
+        $("range").addEventListener("change", (event) => {
+            state.rangeSecs = Number(event.target.value);
+            refresh();
+        });
+        window.addEventListener("resize", renderCharts);
+
+        refresh();
+        state.timer = setInterval(refresh, REFRESH_MS);
+    }
+
+    init();
+})();
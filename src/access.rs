@@ -0,0 +1,181 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use tracing::debug;
+
+use crate::{api::ApiError, config::AccessRules, state::SharedState};
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// The resolved address of the client, stored in the request extensions by
+/// [`filter`] for the middleware and handlers that run after it.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+/// Middleware rejecting clients outside the allow list or inside the deny
+/// list before any other processing, including key checks.
+pub async fn filter(
+    State(state): State<SharedState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let config = state.config();
+    let rules = config.access();
+    let Some(peer) = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+    else {
+        return next.run(request).await;
+    };
+
+    let ip = resolve_client(peer, request.headers(), &rules.trusted_proxies);
+    let mut response = if is_allowed(ip, rules) {
+        request.extensions_mut().insert(ClientIp(ip));
+        next.run(request).await
+    } else {
+        debug!("rejected request from {}", ip);
+        ApiError::AddressNotAllowed(ip).into_response()
+    };
+    // Also reported on the response for the audit log, which runs outside this filter.
+    response.extensions_mut().insert(ClientIp(ip));
+    response
+}
+
+/// Honours `X-Forwarded-For` only when the peer is a trusted proxy. The header
+/// is read from the right, skipping further trusted proxies, so a client
+/// cannot spoof its address by prepending entries.
+fn resolve_client(peer: IpAddr, headers: &HeaderMap, trusted: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let forwarded = headers
+        .get_all(FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+
+    let mut client = peer;
+    for entry in forwarded.iter().rev() {
+        match entry.parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+                if !is_trusted(&ip) {
+                    break;
+                }
+            }
+            // An unparsable hop ends the chain we can vouch for.
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+fn is_allowed(ip: IpAddr, rules: &AccessRules) -> bool {
+    if rules.deny.iter().any(|net| net.contains(&ip)) {
+        return false;
+    }
+    rules.allow.is_empty() || rules.allow.iter().any(|net| net.contains(&ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn nets(values: &[&str]) -> Vec<IpNet> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(FORWARDED_FOR, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn ignores_forwarded_for_from_untrusted_peers() {
+        let trusted = nets(&["10.0.0.0/8"]);
+        let headers = forwarded(&["203.0.113.7"]);
+        assert_eq!(
+            resolve_client(ip("192.0.2.1"), &headers, &trusted),
+            ip("192.0.2.1")
+        );
+        assert_eq!(
+            resolve_client(ip("192.0.2.1"), &headers, &[]),
+            ip("192.0.2.1")
+        );
+    }
+
+    #[test]
+    fn uses_forwarded_for_from_trusted_proxies() {
+        let trusted = nets(&["10.0.0.0/8"]);
+        let proxy = ip("10.0.0.1");
+        assert_eq!(
+            resolve_client(proxy, &forwarded(&["203.0.113.7"]), &trusted),
+            ip("203.0.113.7")
+        );
+        // Without the header the proxy itself is the client.
+        assert_eq!(resolve_client(proxy, &HeaderMap::new(), &trusted), proxy);
+    }
+
+    #[test]
+    fn reads_forwarded_for_from_the_right() {
+        let trusted = nets(&["10.0.0.0/8"]);
+        let proxy = ip("10.0.0.1");
+
+        // A spoofed entry prepended by the client is not used.
+        let headers = forwarded(&["198.51.100.1, 203.0.113.7"]);
+        assert_eq!(resolve_client(proxy, &headers, &trusted), ip("203.0.113.7"));
+
+        // Further trusted proxies in the chain are skipped.
+        let headers = forwarded(&["203.0.113.7, 10.0.0.2", "10.0.0.3"]);
+        assert_eq!(resolve_client(proxy, &headers, &trusted), ip("203.0.113.7"));
+
+        // An unparsable hop stops at the last address that could be vouched for.
+        let headers = forwarded(&["203.0.113.7, unknown, 10.0.0.2"]);
+        assert_eq!(resolve_client(proxy, &headers, &trusted), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn deny_takes_precedence_over_allow() {
+        let rules = AccessRules {
+            allow: nets(&["192.0.2.0/24"]),
+            deny: nets(&["192.0.2.128/25"]),
+            trusted_proxies: Vec::new(),
+        };
+        assert!(is_allowed(ip("192.0.2.1"), &rules));
+        assert!(!is_allowed(ip("192.0.2.200"), &rules));
+        assert!(!is_allowed(ip("198.51.100.1"), &rules));
+    }
+
+    #[test]
+    fn empty_allow_list_allows_everything_not_denied() {
+        let rules = AccessRules {
+            allow: Vec::new(),
+            deny: nets(&["192.0.2.0/24", "2001:db8::/32"]),
+            trusted_proxies: Vec::new(),
+        };
+        assert!(is_allowed(ip("198.51.100.1"), &rules));
+        assert!(is_allowed(ip("::1"), &rules));
+        assert!(!is_allowed(ip("192.0.2.1"), &rules));
+        assert!(!is_allowed(ip("2001:db8::1"), &rules));
+    }
+}
@@ -1,30 +1,252 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    borrow::Cow, collections::HashMap, convert::Infallible, net::IpAddr, sync::Arc, time::Duration,
+};
 
 use axum::{
-    extract::{Query, State},
-    http::HeaderMap,
+    async_trait,
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts, Path, Query, Request, State,
+    },
+    http::{
+        header::{RETRY_AFTER, WWW_AUTHENTICATE},
+        request::Parts,
+        HeaderMap, HeaderValue, StatusCode, Uri,
+    },
+    middleware::Next,
     response::{IntoResponse, Response},
-    Json,
+    routing::{get, post},
+    Json, Router,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use thiserror::Error;
+use tracing::warn;
+use utoipa::ToSchema;
 
 use crate::{
+    access::ClientIp,
+    audit,
     auth::{self, AuthError},
-    collector::SystemSnapshot,
-    state::{AppState, SharedState},
+    catalog,
+    catalog::{CatalogEntry, MetricDescriptor},
+    collector::{self, ProcessDetails, ProcessInfo, SystemSnapshot},
+    health,
+    keys::{Principal, Scope},
+    openapi, ratelimit, reload,
+    series::{self, Aggregation, MetricPath, SeriesError},
+    state::{AppState, ReloadError, SharedState},
+    storage::{self, StorageError},
+    stream,
+    tls::ClientCertificate,
+    tokens, ws,
 };
 
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Prefix of the versioned API; the same routes are also served under `/api`.
+pub const V1_PREFIX: &str = "/api/v1";
+
+/// Routes of the JSON API, relative to [`V1_PREFIX`] or `/api`.
+pub fn router() -> Router<SharedState> {
+    Router::new()
+        .route("/openapi.json", get(openapi::spec))
+        .route("/system", get(system))
+        .route("/history", get(history))
+        .route("/snapshots", get(snapshot_file))
+        .route("/series", get(series))
+        .route("/metrics/catalog", get(metric_catalog))
+        .route("/stream", get(stream::stream))
+        .route("/ws", get(ws::upgrade))
+        .route("/processes/:pid", get(process))
+        .route("/apps", get(apps))
+        .route("/tasks", get(tasks))
+        .route("/webtest", get(webtest))
+        .route("/alerts", get(alerts))
+        .route("/audit", get(audit::audit))
+        .route("/tokens", post(tokens::issue))
+        .route("/agent/status", get(health::status))
+        .route("/agent/metrics", get(health::metrics))
+        .route("/config/reload", post(reload::reload))
+}
+
+/// Maps `/api/v1/...` to the equivalent `/api/...` path, so path based rules
+/// (rate limits, token routes) cover both.
+pub fn unversioned(path: &str) -> Cow<'_, str> {
+    match path.strip_prefix(V1_PREFIX) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => Cow::Owned(format!("/api{rest}")),
+        _ => Cow::Borrowed(path),
+    }
+}
+
+/// Error returned by every API endpoint, rendered as
+/// `{"error": {"code": "...", "message": "..."}}` with the matching status.
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("unauthorized")]
     Unauthorized,
+    #[error("bad request: {0}")]
+    BadRequest(String),
+    #[error("forbidden: key `{key}` lacks the {} scope", .scope.as_str())]
+    Forbidden { key: String, scope: Scope },
+    #[error("forbidden: {0} is not allowed to access this agent")]
+    AddressNotAllowed(IpAddr),
+    #[error("forbidden: token is not valid for {0}")]
+    RouteNotAllowed(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("internal error: {0}")]
+    Internal(String),
+    #[error("too many requests, retry in {}s", retry_after_secs(.0))]
+    TooManyRequests(Duration),
+    #[error("configuration rejected, keeping the current one: {0}")]
+    InvalidConfig(#[from] ReloadError),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Forbidden { .. }
+            | ApiError::AddressNotAllowed(_)
+            | ApiError::RouteNotAllowed(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Storage(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::InvalidConfig(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    /// Stable machine-readable identifier of the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Forbidden { .. } => "forbidden",
+            ApiError::AddressNotAllowed(_) => "address_not_allowed",
+            ApiError::RouteNotAllowed(_) => "route_not_allowed",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Storage(_) => "storage_error",
+            ApiError::Internal(_) => "internal",
+            ApiError::TooManyRequests(_) => "rate_limited",
+            ApiError::InvalidConfig(_) => "invalid_config",
+        }
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        (axum::http::StatusCode::UNAUTHORIZED, self.to_string()).into_response()
+        if matches!(self, ApiError::Storage(_) | ApiError::Internal(_)) {
+            warn!("{}", self);
+        }
+
+        let mut error = ErrorDetail {
+            code: self.code(),
+            message: self.to_string(),
+            retry_after_secs: None,
+        };
+        let mut headers = HeaderMap::new();
+        match &self {
+            ApiError::Unauthorized => {
+                headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            }
+            ApiError::TooManyRequests(retry_after) => {
+                let secs = retry_after_secs(retry_after);
+                error.retry_after_secs = Some(secs);
+                headers.insert(RETRY_AFTER, HeaderValue::from(secs));
+            }
+            _ => {}
+        }
+        (self.status(), headers, Json(ErrorResponse { error })).into_response()
+    }
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorDetail {
+    /// One of `bad_request`, `unauthorized`, `forbidden`, `address_not_allowed`,
+    /// `route_not_allowed`, `not_found`, `invalid_config`, `rate_limited`,
+    /// `storage_error`, `internal`.
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+fn retry_after_secs(retry_after: &Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.0) as u64
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(value: QueryRejection) -> Self {
+        ApiError::BadRequest(value.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(value: PathRejection) -> Self {
+        ApiError::BadRequest(value.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(value: JsonRejection) -> Self {
+        ApiError::BadRequest(value.body_text())
+    }
+}
+
+/// [`Query`] that answers malformed parameters with a JSON `bad_request`.
+#[derive(Debug, FromRequestParts)]
+#[from_request(via(Query), rejection(ApiError))]
+pub struct ApiQuery<T>(pub T);
+
+/// [`Path`] that answers malformed segments with a JSON `bad_request`.
+#[derive(Debug, FromRequestParts)]
+#[from_request(via(Path), rejection(ApiError))]
+pub struct ApiPath<T>(pub T);
+
+/// [`Json`] body that answers malformed input with a JSON `bad_request`.
+#[derive(Debug, FromRequest)]
+#[from_request(via(Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+/// Answers unknown routes with a JSON `not_found`.
+pub async fn not_found(uri: Uri) -> ApiError {
+    ApiError::NotFound(format!("no route for {}", uri.path()))
+}
+
+/// Rejects inverted time ranges and empty pages.
+pub(crate) fn validate_range(
+    from: Option<i64>,
+    to: Option<i64>,
+    limit: Option<usize>,
+) -> Result<(), ApiError> {
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(ApiError::BadRequest(format!(
+                "`from` ({from}) is after `to` ({to})"
+            )));
+        }
+    }
+    if limit == Some(0) {
+        return Err(ApiError::BadRequest(
+            "`limit` must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl From<SeriesError> for ApiError {
+    fn from(value: SeriesError) -> Self {
+        ApiError::BadRequest(value.to_string())
     }
 }
 
@@ -34,13 +256,23 @@ impl From<AuthError> for ApiError {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/system",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Latest snapshot", body = SystemSnapshot),
+        (status = 401, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
 pub async fn system(
     State(state): State<SharedState>,
-    headers: HeaderMap,
-    Query(query): Query<HashMap<String, String>>,
+    caller: Caller,
 ) -> Result<Json<SystemSnapshot>, ApiError> {
-    authorise_with_query(&state, &headers, &query)?;
-    let snapshot = state.latest_snapshot().await;
+    let principal = caller.authorise(Scope::ReadMetrics)?;
+    let mut snapshot = state.latest_snapshot().await;
+    redact_processes(&principal, [&mut snapshot]);
     Ok(Json(snapshot))
 }
 
@@ -49,38 +281,292 @@ pub struct SystemQuery {
     pub limit: Option<usize>,
     pub from: Option<i64>,
     pub to: Option<i64>,
-    #[serde(flatten)]
-    pub auth_params: HashMap<String, String>,
+    pub cursor: Option<i64>,
 }
 
+/// Recent snapshots, newest first. The `x-next-cursor` header is set when
+/// older snapshots may match; the page it leads to can be empty.
+#[utoipa::path(
+    get,
+    path = "/api/v1/history",
+    tag = "metrics",
+    params(
+        ("limit" = Option<usize>, Query, description = "Page size, at most HISTORY_LIMIT"),
+        ("from" = Option<i64>, Query, description = "Start, Unix milliseconds"),
+        ("to" = Option<i64>, Query, description = "End, Unix milliseconds"),
+        ("cursor" = Option<i64>, Query, description = "Value of a previous `x-next-cursor`"),
+    ),
+    responses(
+        (status = 200, body = Vec<SystemSnapshot>,
+            headers(("x-next-cursor" = i64, description = "Cursor of the next page"))),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 500, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
 pub async fn history(
     State(state): State<SharedState>,
-    headers: HeaderMap,
-    Query(query): Query<SystemQuery>,
-) -> Result<Json<Vec<SystemSnapshot>>, ApiError> {
-    authorise_with_query(&state, &headers, &query.auth_params)?;
+    caller: Caller,
+    ApiQuery(query): ApiQuery<SystemQuery>,
+) -> Result<Response, ApiError> {
+    let principal = caller.authorise(Scope::ReadMetrics)?;
 
-    let max = state.config().history_limit();
-    let limit = query.limit.unwrap_or(max).min(max);
-    let mut snapshots = state.history(limit).await;
+    let (mut snapshots, next_cursor) =
+        history_page(&state, query.from, query.to, query.cursor, query.limit).await?;
+    redact_processes(&principal, &mut snapshots);
 
-    // Zaman aralığına göre filtrele
-    if let Some(from) = query.from {
-        snapshots.retain(|s| s.timestamp >= from);
+    let mut response = Json(snapshots).into_response();
+    if let Some(cursor) = next_cursor {
+        response
+            .headers_mut()
+            .insert(NEXT_CURSOR_HEADER, HeaderValue::from(cursor));
     }
-    if let Some(to) = query.to {
-        snapshots.retain(|s| s.timestamp <= to);
+    Ok(response)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SeriesQuery {
+    pub metric: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub step: Option<String>,
+    pub agg: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SeriesResponse {
+    pub metric: String,
+    pub unit: Option<&'static str>,
+    pub agg: &'static str,
+    pub step_ms: Option<u64>,
+    /// `[timestamp, value]` pairs in ascending order.
+    #[schema(value_type = Vec<Vec<f64>>)]
+    pub points: Vec<(i64, f64)>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/series",
+    tag = "metrics",
+    params(
+        ("metric" = String, Query, description = "Metric path, e.g. `disks[/].used_pct`"),
+        ("from" = Option<i64>, Query, description = "Start, Unix milliseconds"),
+        ("to" = Option<i64>, Query, description = "End, Unix milliseconds"),
+        ("step" = Option<String>, Query, description = "Bucket size, e.g. `30s`, `5m`"),
+        ("agg" = Option<String>, Query, description = "avg (default), min, max, p50, p95, p99 or last"),
+    ),
+    responses(
+        (status = 200, body = SeriesResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 500, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn series(
+    State(state): State<SharedState>,
+    caller: Caller,
+    ApiQuery(query): ApiQuery<SeriesQuery>,
+) -> Result<Json<SeriesResponse>, ApiError> {
+    let principal = caller.authorise(Scope::ReadMetrics)?;
+
+    let path: MetricPath = query.metric.parse()?;
+    let step = query.step.as_deref().map(series::parse_step).transpose()?;
+    let aggregation: Aggregation = query.agg.as_deref().unwrap_or("avg").parse()?;
+
+    let mut snapshots = snapshots_in_range(&state, query.from, query.to).await?;
+    redact_processes(&principal, &mut snapshots);
+    let points = series::build_series(&snapshots, &path, step, aggregation);
+
+    Ok(Json(SeriesResponse {
+        metric: path.as_str().to_string(),
+        unit: catalog::describe(path.as_str()).map(|descriptor| descriptor.unit),
+        agg: aggregation.as_str(),
+        step_ms: step.map(|step| step.as_millis() as u64),
+        points,
+    }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CatalogResponse {
+    pub hostname: Option<String>,
+    /// Concrete metric paths available in the latest snapshot.
+    pub metrics: Vec<CatalogEntry>,
+    pub templates: &'static [MetricDescriptor],
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/metrics/catalog",
+    tag = "metrics",
+    responses(
+        (status = 200, body = CatalogResponse),
+        (status = 401, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn metric_catalog(
+    State(state): State<SharedState>,
+    caller: Caller,
+) -> Result<Json<CatalogResponse>, ApiError> {
+    caller.authorise(Scope::ReadMetrics)?;
+    let snapshot = state.latest_snapshot().await;
+    Ok(Json(CatalogResponse {
+        metrics: catalog::catalog(&snapshot),
+        hostname: snapshot.hostname,
+        templates: catalog::descriptors(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProcessQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProcessResponse {
+    pub pid: i64,
+    pub running: bool,
+    /// Entry in the latest top list, if the process is in it.
+    pub current: Option<ProcessInfo>,
+    /// Live details, `null` once the process has exited.
+    pub details: Option<ProcessDetails>,
+    pub last_seen: Option<i64>,
+    pub top_list: Vec<TopListSpan>,
+    pub history: Vec<ProcessSample>,
+}
+
+/// A period the process spent in the top list; `left` is `null` if it is still there.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TopListSpan {
+    pub entered: i64,
+    pub left: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProcessSample {
+    pub timestamp: i64,
+    pub cpu_pct: f64,
+    pub memory_mb: u64,
+    pub disk_read_kbps: f64,
+    pub disk_write_kbps: f64,
+    pub thread_count: Option<usize>,
+}
+
+/// Everything known about one process: its latest top-list entry, its history
+/// across stored snapshots, when it was in the top list and live details such
+/// as the command line and parent/children.
+#[utoipa::path(
+    get,
+    path = "/api/v1/processes/{pid}",
+    tag = "processes",
+    params(
+        ("pid" = i64, Path, description = "Process id"),
+        ("from" = Option<i64>, Query, description = "Start of the history, Unix milliseconds"),
+        ("to" = Option<i64>, Query, description = "End of the history, Unix milliseconds"),
+    ),
+    responses(
+        (status = 200, body = ProcessResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, description = "Process unknown", body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn process(
+    State(state): State<SharedState>,
+    ApiPath(pid): ApiPath<i64>,
+    caller: Caller,
+    ApiQuery(query): ApiQuery<ProcessQuery>,
+) -> Result<Json<ProcessResponse>, ApiError> {
+    caller.authorise(Scope::ReadProcesses)?;
+
+    let snapshots = snapshots_in_range(&state, query.from, query.to).await?;
+    let latest = state.latest_snapshot().await;
+    let current = find_process(&latest, pid).cloned();
+
+    let mut history = Vec::new();
+    let mut top_list = Vec::new();
+    let mut entered: Option<i64> = None;
+    let mut last_seen: Option<i64> = None;
+    for snapshot in &snapshots {
+        match find_process(snapshot, pid) {
+            Some(process) => {
+                entered.get_or_insert(snapshot.timestamp);
+                last_seen = Some(snapshot.timestamp);
+                history.push(ProcessSample {
+                    timestamp: snapshot.timestamp,
+                    cpu_pct: process.cpu_pct,
+                    memory_mb: process.memory_mb,
+                    disk_read_kbps: process.disk_read_kbps,
+                    disk_write_kbps: process.disk_write_kbps,
+                    thread_count: process.thread_count,
+                });
+            }
+            None => {
+                if let Some(start) = entered.take() {
+                    top_list.push(TopListSpan {
+                        entered: start,
+                        left: Some(snapshot.timestamp),
+                    });
+                }
+            }
+        }
+    }
+    if let Some(start) = entered {
+        top_list.push(TopListSpan {
+            entered: start,
+            left: None,
+        });
     }
 
-    Ok(Json(snapshots))
+    let details = tokio::task::spawn_blocking(move || collector::inspect_process(pid))
+        .await
+        .ok()
+        .flatten();
+
+    if details.is_none() && current.is_none() && history.is_empty() {
+        return Err(ApiError::NotFound(format!("process {pid}")));
+    }
+
+    Ok(Json(ProcessResponse {
+        pid,
+        running: details.is_some(),
+        current,
+        details,
+        last_seen,
+        top_list,
+        history,
+    }))
+}
+
+fn find_process(snapshot: &SystemSnapshot, pid: i64) -> Option<&ProcessInfo> {
+    snapshot
+        .top_processes
+        .iter()
+        .find(|process| process.pid == pid)
 }
 
+/// The latest snapshot grouped by subsystem.
+#[utoipa::path(
+    get,
+    path = "/api/v1/apps",
+    tag = "processes",
+    responses(
+        (status = 200, body = Object),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
 pub async fn apps(
     State(state): State<SharedState>,
-    headers: HeaderMap,
-    Query(query): Query<HashMap<String, String>>,
+    caller: Caller,
 ) -> Result<Json<serde_json::Value>, ApiError> {
-    authorise_with_query(&state, &headers, &query)?;
+    caller.authorise(Scope::ReadProcesses)?;
     let snapshot = state.latest_snapshot().await;
     Ok(Json(json!({
         "timestamp": snapshot.timestamp,
@@ -114,56 +600,312 @@ pub async fn apps(
     })))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/tasks",
+    tag = "admin",
+    responses(
+        (status = 200, body = Object),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
 pub async fn tasks(
-    State(state): State<SharedState>,
-    headers: HeaderMap,
-    Query(query): Query<HashMap<String, String>>,
+    State(_state): State<SharedState>,
+    caller: Caller,
 ) -> Result<Json<serde_json::Value>, ApiError> {
-    authorise_with_query(&state, &headers, &query)?;
+    caller.authorise(Scope::Admin)?;
     Ok(Json(json!({ "tasks": [] })))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/webtest",
+    tag = "admin",
+    responses(
+        (status = 200, body = Object),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
 pub async fn webtest(
-    State(state): State<SharedState>,
-    headers: HeaderMap,
-    Query(query): Query<HashMap<String, String>>,
+    State(_state): State<SharedState>,
+    caller: Caller,
 ) -> Result<Json<serde_json::Value>, ApiError> {
-    authorise_with_query(&state, &headers, &query)?;
+    caller.authorise(Scope::Admin)?;
     Ok(Json(json!({ "tests": [] })))
 }
 
+/// Failures of the agent itself (collection sections, storage, reloads) that
+/// have not recovered yet. WebSocket clients can subscribe to the `alerts`
+/// topic for each change.
+#[utoipa::path(
+    get,
+    path = "/api/v1/alerts",
+    tag = "metrics",
+    responses(
+        (status = 200, body = AlertsResponse),
+        (status = 401, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
 pub async fn alerts(
     State(state): State<SharedState>,
-    headers: HeaderMap,
-    Query(query): Query<HashMap<String, String>>,
-) -> Result<Json<serde_json::Value>, ApiError> {
-    authorise_with_query(&state, &headers, &query)?;
-    Ok(Json(json!({ "alerts": [] })))
+    caller: Caller,
+) -> Result<Json<AlertsResponse>, ApiError> {
+    caller.authorise(Scope::ReadMetrics)?;
+    Ok(Json(AlertsResponse {
+        alerts: state.health().firing_alerts(),
+    }))
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AlertsResponse {
+    pub alerts: Vec<health::AlertEvent>,
+}
+
+/// The rolling snapshot file, or with `from`/`to` every stored snapshot in
+/// that range including archives.
+#[utoipa::path(
+    get,
+    path = "/api/v1/snapshots",
+    tag = "metrics",
+    params(
+        ("from" = Option<i64>, Query, description = "Start, Unix milliseconds"),
+        ("to" = Option<i64>, Query, description = "End, Unix milliseconds"),
+    ),
+    responses(
+        (status = 200, body = storage::SnapshotHistory),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 404, description = "Nothing stored yet", body = ErrorResponse),
+        (status = 500, body = ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
 pub async fn snapshot_file(
     State(state): State<SharedState>,
-    headers: HeaderMap,
-    Query(query): Query<HashMap<String, String>>,
+    caller: Caller,
+    ApiQuery(query): ApiQuery<SystemQuery>,
 ) -> Result<Json<serde_json::Value>, ApiError> {
-    authorise_with_query(&state, &headers, &query)?;
+    let principal = caller.authorise(Scope::ReadMetrics)?;
+
+    // Zaman aralığı verilmişse arşivlerden de oku
+    if query.from.is_some() || query.to.is_some() {
+        validate_range(query.from, query.to, None)?;
+        let mut snapshots = storage::load_range(&state.config(), query.from, query.to).await?;
+        redact_processes(&principal, &mut snapshots);
+        return Ok(Json(json!({ "snapshots": snapshots })));
+    }
 
     // JSON dosyasını oku ve döndür
     let file_path = state.config().snapshot_dir().join("system_snapshot.json");
 
-    match tokio::fs::read_to_string(&file_path).await {
-        Ok(content) => match serde_json::from_str::<serde_json::Value>(&content) {
-            Ok(json_data) => Ok(Json(json_data)),
-            Err(_) => Ok(Json(json!({ "snapshots": [] }))),
-        },
-        Err(_) => Ok(Json(json!({ "snapshots": [] }))),
+    // A missing file means nothing was stored yet; an unreadable one is a storage failure.
+    let content = match tokio::fs::read_to_string(&file_path).await {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ApiError::NotFound(
+                "no snapshots have been stored yet".to_string(),
+            ))
+        }
+        Err(err) => return Err(StorageError::from(err).into()),
+    };
+    let mut json_data =
+        serde_json::from_str::<serde_json::Value>(&content).map_err(StorageError::from)?;
+    if !principal.allows(Scope::ReadProcesses) {
+        // The file is served as stored, so its process lists are cleared here.
+        let stored = json_data
+            .get_mut("snapshots")
+            .and_then(serde_json::Value::as_array_mut);
+        for snapshot in stored.into_iter().flatten() {
+            if let Some(processes) = snapshot.get_mut("top_processes") {
+                *processes = json!([]);
+            }
+        }
+    }
+    Ok(Json(json_data))
+}
+
+/// Clears the process lists of `snapshots` unless `principal` holds
+/// `read-processes`: `read-metrics` covers host-wide figures only.
+pub(crate) fn redact_processes<'a>(
+    principal: &Principal,
+    snapshots: impl IntoIterator<Item = &'a mut SystemSnapshot>,
+) {
+    if !principal.allows(Scope::ReadProcesses) {
+        for snapshot in snapshots {
+            snapshot.top_processes.clear();
+        }
     }
 }
 
-fn authorise_with_query(
+/// One page of history, newest first, plus the cursor for the next page if
+/// more snapshots may match. The limit is applied after the time filter.
+/// Storage is only read when the page reaches past the in-memory window.
+pub(crate) async fn history_page(
+    state: &Arc<AppState>,
+    from: Option<i64>,
+    to: Option<i64>,
+    cursor: Option<i64>,
+    limit: Option<usize>,
+) -> Result<(Vec<SystemSnapshot>, Option<i64>), ApiError> {
+    validate_range(from, to, limit)?;
+    let max = state.config().history_limit();
+    let limit = limit.unwrap_or(max).min(max);
+
+    // Cursor, bir önceki sayfanın en eski timestamp'i; sonraki sayfa ondan daha eski
+    let before_cursor = cursor
+        .map(|cursor| {
+            cursor
+                .checked_sub(1)
+                .ok_or_else(|| ApiError::BadRequest(format!("invalid `cursor` ({cursor})")))
+        })
+        .transpose()?;
+    let to = match (to, before_cursor) {
+        (Some(to), Some(before)) => Some(to.min(before)),
+        (to, before) => to.or(before),
+    };
+
+    // Fazladan bir kayıt, sonraki sayfanın olup olmadığını gösterir
+    let (mut snapshots, covered) = state.history_range(from, to, limit + 1).await;
+    let more = if snapshots.len() > limit {
+        true
+    } else if snapshots.len() == limit {
+        // A full page from memory; older matches can only be on disk, which
+        // the next page reads (and may find empty).
+        !covered
+    } else if covered {
+        false
+    } else {
+        // The page reaches past the oldest in-memory snapshot.
+        snapshots = storage::load_page(&state.config(), from, to, limit + 1).await?;
+        snapshots.len() > limit
+    };
+
+    snapshots.truncate(limit);
+    let next_cursor = more
+        .then(|| snapshots.last().map(|s| s.timestamp))
+        .flatten();
+    Ok((snapshots, next_cursor))
+}
+
+/// Snapshots within `from..=to` in ascending order. Without `from`, only the
+/// in-memory window is used; older ranges are read from storage.
+pub(crate) async fn snapshots_in_range(
+    state: &Arc<AppState>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Vec<SystemSnapshot>, ApiError> {
+    validate_range(from, to, None)?;
+    let from = match from {
+        Some(from) => Some(from),
+        None => state.oldest_timestamp().await,
+    };
+
+    let (mut in_memory, covered) = state.history_range(from, to, usize::MAX).await;
+    in_memory.reverse();
+    if covered {
+        return Ok(in_memory);
+    }
+
+    Ok(storage::load_range(&state.config(), from, to).await?)
+}
+
+/// Caller of the request, resolved once by [`authenticate`]. `None` when the
+/// request carries no valid credentials.
+#[derive(Debug, Clone, Default)]
+pub struct Caller(pub Option<Principal>);
+
+impl Caller {
+    /// The authenticated principal, regardless of its scopes.
+    pub fn principal(self) -> Result<Principal, ApiError> {
+        self.0.ok_or(ApiError::Unauthorized)
+    }
+
+    /// The authenticated principal, provided it holds `scope`.
+    pub fn authorise(self, scope: Scope) -> Result<Principal, ApiError> {
+        let principal = self.principal()?;
+        if !principal.allows(scope) {
+            return Err(ApiError::Forbidden {
+                key: principal.name,
+                scope,
+            });
+        }
+        Ok(principal)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Infallible> {
+        Ok(parts
+            .extensions
+            .get::<Caller>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Middleware resolving the caller once, inside the access filter and rate
+/// limiter so rejected clients never get their credentials compared, and
+/// charging history reads of authenticated callers to the history budget. The
+/// caller is also attached to the response for the audit log.
+pub async fn authenticate(
+    State(state): State<SharedState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let query = Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .map(|Query(query)| query)
+        .unwrap_or_default();
+    // Set by `tls::ClientCertAcceptor` on connections with a verified certificate.
+    let certificate = request
+        .extensions()
+        .get::<Option<ClientCertificate>>()
+        .and_then(Option::as_ref);
+    let caller = Caller(identify(&state, request.headers(), &query, certificate).ok());
+
+    // The shared history budget is only charged for authenticated callers, so
+    // anonymous requests cannot exhaust it for everyone else.
+    if caller.0.is_some() && ratelimit::is_history_path(&unversioned(request.uri().path())) {
+        let ip = request
+            .extensions()
+            .get::<ClientIp>()
+            .map(|ClientIp(ip)| *ip);
+        let config = state.config();
+        if let Err(retry_after) = state.rate_limiter().check_history(ip, config.rate_limits()) {
+            let mut response = ApiError::TooManyRequests(retry_after).into_response();
+            response.extensions_mut().insert(caller);
+            return response;
+        }
+    }
+    request.extensions_mut().insert(caller.clone());
+
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(caller);
+    response
+}
+
+/// Resolves the caller from the session cookie or an API key, without any
+/// scope check.
+fn identify(
     state: &Arc<AppState>,
     headers: &HeaderMap,
     query: &HashMap<String, String>,
-) -> Result<(), ApiError> {
-    auth::ensure_authorized_with_query(headers, query, state.config()).map_err(ApiError::from)
+    certificate: Option<&ClientCertificate>,
+) -> Result<Principal, ApiError> {
+    // Dashboard oturum çerezi de kabul edilir
+    let session = auth::session_token(headers).and_then(|token| state.sessions().principal(token));
+    if let Some(principal) = session {
+        return Ok(principal);
+    }
+    // An API key takes precedence; a client certificate is the fallback.
+    let config = state.config();
+    auth::ensure_authorized_with_query(headers, query, &config, &state.keys(), &state.tokens())
+        .or_else(|err| auth::client_certificate(certificate, &config).ok_or(ApiError::from(err)))
 }
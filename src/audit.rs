@@ -0,0 +1,395 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, BufRead, BufReader},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+};
+use tracing::{error, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    access::ClientIp,
+    api::{ApiError, ApiQuery, Caller},
+    config::AuditConfig,
+    keys::Scope,
+    state::SharedState,
+};
+
+// Records waiting to be written; further requests are dropped from the log
+// (with a warning) rather than slowing down the API.
+const QUEUE_CAPACITY: usize = 1024;
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthOutcome {
+    Granted,
+    Unauthorized,
+    Forbidden,
+    /// No credentials were needed or presented (login page, static assets).
+    None,
+}
+
+/// One request as written to the audit log. Only the key name is recorded,
+/// never the presented secret.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditRecord {
+    pub timestamp: i64,
+    pub client_ip: Option<IpAddr>,
+    pub method: String,
+    pub route: Option<String>,
+    pub path: String,
+    pub key: Option<String>,
+    pub auth: AuthOutcome,
+    pub status: u16,
+    pub latency_ms: f64,
+}
+
+/// Handle to the background task appending records to the audit file.
+#[derive(Debug)]
+pub struct AuditLog {
+    sender: Option<mpsc::Sender<AuditRecord>>,
+    dropped: AtomicU64,
+    closing: Arc<Notify>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl AuditLog {
+    pub fn start(config: Option<&AuditConfig>) -> Self {
+        let closing = Arc::new(Notify::new());
+        let Some(config) = config.cloned() else {
+            return Self {
+                sender: None,
+                dropped: AtomicU64::new(0),
+                closing,
+                writer: Mutex::new(None),
+            };
+        };
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let writer = tokio::spawn(write_loop(config, receiver, closing.clone()));
+        Self {
+            sender: Some(sender),
+            dropped: AtomicU64::new(0),
+            closing,
+            writer: Mutex::new(Some(writer)),
+        }
+    }
+
+    /// Writes the records still queued and flushes the file. Records arriving
+    /// afterwards are dropped.
+    pub async fn close(&self) {
+        self.closing.notify_one();
+        let writer = self.writer.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(writer) = writer {
+            let _ = writer.await;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Records dropped because the writer could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, record: AuditRecord) {
+        if let Some(sender) = &self.sender {
+            // After `close` the record is lost; the log is shutting down.
+            if let Err(mpsc::error::TrySendError::Full(_)) = sender.try_send(record) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!("audit log queue is full, dropping record");
+            }
+        }
+    }
+}
+
+/// Middleware recording every request with its caller, outcome and latency.
+pub async fn record(State(state): State<SharedState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    // Static assets and probes would only flood the log.
+    if path.starts_with("/ui/assets/") || path == "/healthz" || path == "/readyz" {
+        return next.run(request).await;
+    }
+
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string());
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    let response = next.run(request).await;
+
+    // Set by `api::authenticate`; absent when the request was rejected first.
+    let key = response
+        .extensions()
+        .get::<Caller>()
+        .and_then(|Caller(principal)| principal.as_ref())
+        .map(|principal| principal.name.clone());
+
+    let status = response.status();
+    let auth = match status {
+        StatusCode::UNAUTHORIZED => AuthOutcome::Unauthorized,
+        StatusCode::FORBIDDEN => AuthOutcome::Forbidden,
+        _ if key.is_some() => AuthOutcome::Granted,
+        _ => AuthOutcome::None,
+    };
+    // The access filter reports the resolved client address (after X-Forwarded-For).
+    let client_ip = response
+        .extensions()
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| *ip)
+        .or(peer);
+
+    state.audit().record(AuditRecord {
+        timestamp: Utc::now().timestamp_millis(),
+        client_ip,
+        method,
+        route,
+        path,
+        key,
+        auth,
+        status: status.as_u16(),
+        latency_ms: (started.elapsed().as_secs_f64() * 1e6).round() / 1000.0,
+    });
+    response
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub key: Option<String>,
+    pub client_ip: Option<IpAddr>,
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.from.is_none_or(|from| record.timestamp >= from)
+            && self.to.is_none_or(|to| record.timestamp <= to)
+            && self
+                .key
+                .as_ref()
+                .is_none_or(|key| record.key.as_ref() == Some(key))
+            && self.client_ip.is_none_or(|ip| record.client_ip == Some(ip))
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|prefix| record.path.starts_with(prefix.as_str()))
+            && self.status.is_none_or(|status| record.status == status)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditResponse {
+    pub records: Vec<AuditRecord>,
+}
+
+/// Audit records matching the filters, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/audit",
+    tag = "admin",
+    params(
+        ("from" = Option<i64>, Query, description = "Start, Unix milliseconds"),
+        ("to" = Option<i64>, Query, description = "End, Unix milliseconds"),
+        ("key" = Option<String>, Query, description = "Key name"),
+        ("client_ip" = Option<String>, Query, description = "Client address"),
+        ("path" = Option<String>, Query, description = "Path prefix"),
+        ("status" = Option<u16>, Query, description = "HTTP status"),
+        ("limit" = Option<usize>, Query, description = "Maximum records, default 100, at most 1000"),
+    ),
+    responses(
+        (status = 200, body = AuditResponse),
+        (status = 401, body = api::ErrorResponse),
+        (status = 403, body = api::ErrorResponse),
+        (status = 404, description = "Audit log disabled", body = api::ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn audit(
+    State(state): State<SharedState>,
+    caller: Caller,
+    ApiQuery(query): ApiQuery<AuditQuery>,
+) -> Result<Json<AuditResponse>, ApiError> {
+    caller.authorise(Scope::Admin)?;
+    let config = state
+        .config()
+        .audit()
+        .cloned()
+        .ok_or_else(|| ApiError::NotFound("audit log is disabled".to_string()))?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .clamp(1, MAX_QUERY_LIMIT);
+    let records = tokio::task::spawn_blocking(move || read_records(&config, &query, limit))
+        .await
+        .map_err(|err| ApiError::Internal(err.to_string()))?
+        .map_err(|err| ApiError::Internal(format!("could not read audit log: {err}")))?;
+
+    Ok(Json(AuditResponse { records }))
+}
+
+fn read_records(
+    config: &AuditConfig,
+    query: &AuditQuery,
+    limit: usize,
+) -> io::Result<Vec<AuditRecord>> {
+    let mut records = Vec::new();
+    for index in 0..=config.max_files {
+        let file = match fs::File::open(rotated_path(&config.path, index)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        let mut lines = Vec::new();
+        for line in BufReader::new(file).lines() {
+            lines.push(line?);
+        }
+        // A partially written last line is skipped rather than failing the query.
+        for record in lines
+            .iter()
+            .rev()
+            .filter_map(|line| serde_json::from_str::<AuditRecord>(line).ok())
+            .filter(|record| query.matches(record))
+        {
+            records.push(record);
+            if records.len() >= limit {
+                return Ok(records);
+            }
+        }
+    }
+    Ok(records)
+}
+
+async fn write_loop(
+    config: AuditConfig,
+    mut receiver: mpsc::Receiver<AuditRecord>,
+    closing: Arc<Notify>,
+) {
+    let mut file = match open(&config.path).await {
+        Ok(file) => file,
+        Err(err) => {
+            error!(
+                "audit log disabled, cannot open {}: {}",
+                config.path.display(),
+                err
+            );
+            return;
+        }
+    };
+    let mut size = file.metadata().await.map(|meta| meta.len()).unwrap_or(0);
+
+    loop {
+        let record = tokio::select! {
+            record = receiver.recv() => match record {
+                Some(record) => record,
+                None => break,
+            },
+            // Stop accepting records; the queued ones are still received.
+            _ = closing.notified() => {
+                receiver.close();
+                continue;
+            }
+        };
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("could not serialise audit record: {}", err);
+                continue;
+            }
+        };
+        line.push(b'\n');
+
+        if size > 0 && size + line.len() as u64 > config.max_bytes {
+            match rotate(&config).await.and(open(&config.path).await) {
+                Ok(rotated) => {
+                    file = rotated;
+                    size = 0;
+                }
+                Err(err) => warn!("could not rotate audit log: {}", err),
+            }
+        }
+
+        match file.write_all(&line).await {
+            Ok(()) => size += line.len() as u64,
+            Err(err) => warn!("could not write audit record: {}", err),
+        }
+    }
+
+    if let Err(err) = file.flush().await {
+        warn!("could not flush audit log: {}", err);
+    }
+}
+
+async fn open(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+/// Shifts `audit.jsonl` to `audit.jsonl.1`, `.1` to `.2` and so on, dropping
+/// the oldest file beyond `max_files`.
+async fn rotate(config: &AuditConfig) -> io::Result<()> {
+    if config.max_files == 0 {
+        return tokio::fs::remove_file(&config.path).await;
+    }
+    for index in (1..config.max_files).rev() {
+        let from = rotated_path(&config.path, index);
+        if tokio::fs::try_exists(&from).await? {
+            tokio::fs::rename(&from, rotated_path(&config.path, index + 1)).await?;
+        }
+    }
+    tokio::fs::rename(&config.path, rotated_path(&config.path, 1)).await
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
@@ -1,7 +1,23 @@
-use axum::http::{header::AUTHORIZATION, HeaderMap};
-use std::collections::HashMap;
+use axum::http::{
+    header::{AUTHORIZATION, COOKIE},
+    HeaderMap,
+};
+use rand::RngCore;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    keys::{KeyStore, Principal, Scope},
+    tls::ClientCertificate,
+    tokens::TokenSigner,
+};
+
+// Name reported for the legacy `SYSTEM_API_KEY`.
+const DEFAULT_KEY_NAME: &str = "default";
 
 #[derive(Debug, thiserror::Error)]
 pub enum AuthError {
@@ -14,43 +30,139 @@ pub fn ensure_authorized_with_query(
     headers: &HeaderMap,
     query_params: &HashMap<String, String>,
     config: &Config,
-) -> Result<(), AuthError> {
-    let expected = config.api_key();
-    if expected.is_empty() {
-        return Ok(());
+    keys: &KeyStore,
+    tokens: &TokenSigner,
+) -> Result<Principal, AuthError> {
+    if config.api_key().is_none() && config.api_keys().is_empty() && !keys.is_configured() {
+        return Ok(Principal::new("anonymous", vec![Scope::Admin]));
     }
 
-    // Önce header'dan kontrol et
-    if let Some(header_value) = headers.get(AUTHORIZATION) {
-        if let Ok(header_str) = header_value.to_str() {
-            if let Some(provided) = header_str
-                .strip_prefix("Bearer ")
-                .or_else(|| header_str.strip_prefix("bearer "))
-            {
-                let provided = provided.trim();
-                if subtle_equals(provided.as_bytes(), expected.as_bytes()) {
-                    return Ok(());
-                }
+    presented_credentials(headers, query_params)
+        .find_map(|(provided, in_query)| {
+            if let Some(claims) = tokens.verify(provided) {
+                return Some(claims.principal());
             }
-        }
-    }
+            // Long-lived keys leak through URLs (logs, browser history); signed
+            // tokens are the replacement for query parameters.
+            if in_query && !config.tokens().keys_in_query {
+                return None;
+            }
+            authenticate_key(provided, config, keys)
+        })
+        .ok_or(AuthError::Unauthorized)
+}
 
-    // Query parameter'dan kontrol et
-    if let Some(api_token) = query_params
+/// Credentials sent with the request, the `Authorization` header first, each
+/// flagged with whether it came from a query parameter.
+pub fn presented_credentials<'a>(
+    headers: &'a HeaderMap,
+    query_params: &'a HashMap<String, String>,
+) -> impl Iterator<Item = (&'a str, bool)> {
+    // Önce header'dan, sonra query parameter'dan kontrol et
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .strip_prefix("Bearer ")
+                .or_else(|| value.strip_prefix("bearer "))
+        })
+        .map(|value| (value.trim(), false));
+    let from_query = query_params
         .get("api_token")
         .or_else(|| query_params.get("apitoken"))
         .or_else(|| query_params.get("token"))
         .or_else(|| query_params.get("key"))
+        .map(|value| (value.as_str(), true));
+
+    [bearer, from_query].into_iter().flatten()
+}
+
+/// Resolves an API key: the legacy `SYSTEM_API_KEY` grants every scope, named
+/// keys from the config file or the key file grant the scopes listed there.
+fn authenticate_key(provided: &str, config: &Config, keys: &KeyStore) -> Option<Principal> {
+    if config
+        .api_key()
+        .is_some_and(|expected| expected.verify(provided))
     {
-        if subtle_equals(api_token.as_bytes(), expected.as_bytes()) {
-            return Ok(());
-        }
+        return Some(Principal::new(DEFAULT_KEY_NAME, vec![Scope::Admin]));
     }
+    crate::keys::authenticate(config.api_keys(), provided).or_else(|| keys.authenticate(provided))
+}
+
+pub const SESSION_COOKIE: &str = "agent_session";
+
+/// Authenticates a request by its verified client certificate (mTLS).
+pub fn client_certificate(
+    certificate: Option<&ClientCertificate>,
+    config: &Config,
+) -> Option<Principal> {
+    let tls = config.tls()?;
+    tls.client_ca.as_ref()?;
+    Some(Principal::new(
+        format!("cert:{}", certificate?.subject),
+        tls.client_scopes.clone(),
+    ))
+}
 
-    Err(AuthError::Unauthorized)
+/// Dashboard login sessions, keyed by the random token stored in the session
+/// cookie. A session carries the scopes of the secret used to log in.
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, (Instant, Principal)>>,
+}
+
+impl SessionStore {
+    pub fn create(&self, ttl: Duration, principal: Principal) -> String {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.retain(|_, (expires, _)| *expires > now);
+        sessions.insert(token.clone(), (now + ttl, principal));
+        token
+    }
+
+    pub fn principal(&self, token: &str) -> Option<Principal> {
+        let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions
+            .get(token)
+            .filter(|(expires, _)| *expires > Instant::now())
+            .map(|(_, principal)| principal.clone())
+    }
+
+    pub fn revoke(&self, token: &str) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.remove(token);
+    }
+}
+
+pub fn session_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, token)| token)
+}
+
+/// Checks the secret entered on the dashboard login page, which may be an API
+/// key or the optional UI password. The password only grants read access.
+pub fn verify_login_secret(secret: &str, config: &Config, keys: &KeyStore) -> Option<Principal> {
+    if let Some(principal) = authenticate_key(secret, config, keys) {
+        return Some(principal);
+    }
+    config
+        .ui_password()
+        .filter(|password| password.verify(secret))
+        .map(|_| Principal::new("ui", vec![Scope::ReadMetrics, Scope::ReadProcesses]))
 }
 
-fn subtle_equals(lhs: &[u8], rhs: &[u8]) -> bool {
+pub(crate) fn subtle_equals(lhs: &[u8], rhs: &[u8]) -> bool {
     use subtle::ConstantTimeEq;
 
     if lhs.len() != rhs.len() {
@@ -0,0 +1,262 @@
+//! Metric catalog derived from the `SystemSnapshot` schema: every numeric
+//! field becomes a metric, its doc comment the description. Units follow the
+//! field-name suffix (`_pct`, `_mb`, `_kbps`, ...), so new fields are listed
+//! without touching this module.
+
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::{
+    openapi::{Object, RefOr, Schema, SchemaType},
+    OpenApi, ToSchema,
+};
+
+use crate::{collector::SystemSnapshot, openapi::ApiDoc, series::IDENTITY_FIELDS};
+
+type Schemas = BTreeMap<String, RefOr<Schema>>;
+
+/// Numeric fields that identify or date a snapshot rather than measure anything.
+const NOT_METRICS: [&str; 2] = ["timestamp", "parent_pid"];
+/// Arrays whose members change on every collection; only listed as templates.
+const VOLATILE_ARRAYS: [&str; 1] = ["top_processes"];
+/// Counters whose names do not end in `_total`.
+const COUNTERS: [&str; 3] = ["uptime_seconds", "failures", "overruns"];
+const UNITS: [(&str, &str); 9] = [
+    ("_pct", "%"),
+    ("_mb", "MB"),
+    ("_gb", "GB"),
+    ("_kbps", "kbps"),
+    ("_bytes", "bytes"),
+    ("_celsius", "Celsius"),
+    ("_ms", "ms"),
+    ("_seconds", "s"),
+    ("_cores", "count"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    Gauge,
+    Counter,
+}
+
+/// Describes one numeric field of [`SystemSnapshot`]. Fields inside arrays use a
+/// `{placeholder}` for the element selector, e.g. `disks[{mount_point}].used_pct`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MetricDescriptor {
+    pub template: String,
+    #[serde(rename = "type")]
+    pub kind: MetricKind,
+    pub unit: &'static str,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CatalogEntry {
+    pub path: String,
+    pub template: String,
+    #[serde(rename = "type")]
+    pub kind: MetricKind,
+    pub unit: &'static str,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+}
+
+pub fn descriptors() -> &'static [MetricDescriptor] {
+    static DESCRIPTORS: OnceLock<Vec<MetricDescriptor>> = OnceLock::new();
+    DESCRIPTORS.get_or_init(|| {
+        let schemas = ApiDoc::openapi()
+            .components
+            .map(|components| components.schemas)
+            .unwrap_or_default();
+        let mut descriptors = Vec::new();
+        if let Some(Schema::Object(snapshot)) = schemas
+            .get("SystemSnapshot")
+            .and_then(|snapshot| resolve(&schemas, snapshot))
+        {
+            collect(&schemas, snapshot, "", &mut descriptors);
+        }
+        descriptors
+    })
+}
+
+/// Finds the descriptor a concrete path such as `disks[/].used_pct` belongs to.
+pub fn describe(path: &str) -> Option<&'static MetricDescriptor> {
+    descriptors()
+        .iter()
+        .find(|descriptor| match split_template(&descriptor.template) {
+            Some((prefix, _, suffix)) => {
+                path.len() > prefix.len() + suffix.len()
+                    && path.starts_with(prefix)
+                    && path.ends_with(suffix)
+            }
+            None => descriptor.template == path,
+        })
+}
+
+/// Expands every descriptor into concrete paths for the disks, interfaces, GPUs,
+/// cores and collection sections present in `snapshot`. Processes are only listed as templates in
+/// [`descriptors`] because the top list changes on every collection.
+pub fn catalog(snapshot: &SystemSnapshot) -> Vec<CatalogEntry> {
+    let Ok(value) = serde_json::to_value(snapshot) else {
+        return Vec::new();
+    };
+    let mut entries = Vec::new();
+
+    for descriptor in descriptors() {
+        if VOLATILE_ARRAYS
+            .iter()
+            .any(|array| descriptor.template.starts_with(&format!("{array}[")))
+        {
+            continue;
+        }
+        let Some((prefix, placeholder, suffix)) = split_template(&descriptor.template) else {
+            entries.push(entry(descriptor, descriptor.template.clone(), None));
+            continue;
+        };
+
+        let field = prefix.strip_suffix('[').unwrap_or(prefix);
+        let items = field
+            .split('.')
+            .try_fold(&value, |value, field| value.get(field))
+            .and_then(Value::as_array);
+        for (position, item) in items.into_iter().flatten().enumerate() {
+            let instance = match item.get(placeholder) {
+                Some(Value::String(instance)) => instance.clone(),
+                Some(Value::Number(instance)) => instance.to_string(),
+                // Arrays of plain numbers are addressed by position.
+                _ => position.to_string(),
+            };
+            let path = format!("{prefix}{instance}{suffix}");
+            entries.push(entry(descriptor, path, Some(instance)));
+        }
+    }
+
+    entries
+}
+
+/// Walks the properties of `schema` and adds a descriptor for every numeric
+/// field, descending into nested objects and arrays.
+fn collect(
+    schemas: &Schemas,
+    object: &Object,
+    prefix: &str,
+    descriptors: &mut Vec<MetricDescriptor>,
+) {
+    for (field, property) in &object.properties {
+        let template = if prefix.is_empty() {
+            field.clone()
+        } else {
+            format!("{prefix}.{field}")
+        };
+        match resolve(schemas, property) {
+            Some(Schema::Object(property)) => match property.schema_type {
+                SchemaType::Integer | SchemaType::Number
+                    if !NOT_METRICS.contains(&field.as_str())
+                        && !IDENTITY_FIELDS.contains(&field.as_str()) =>
+                {
+                    descriptors.push(descriptor(template, field, property));
+                }
+                SchemaType::Object => collect(schemas, property, &template, descriptors),
+                _ => {}
+            },
+            Some(Schema::Array(array)) => match resolve(schemas, &array.items) {
+                Some(Schema::Object(items))
+                    if matches!(items.schema_type, SchemaType::Integer | SchemaType::Number) =>
+                {
+                    // The field's doc comment sits on the array, not its items.
+                    let mut item = items.clone();
+                    if array.description.is_some() {
+                        item.description.clone_from(&array.description);
+                    }
+                    descriptors.push(descriptor(format!("{template}[{{index}}]"), field, &item));
+                }
+                Some(Schema::Object(items)) => {
+                    let identity = IDENTITY_FIELDS
+                        .iter()
+                        .find(|id| items.properties.contains_key(**id))
+                        .copied()
+                        .unwrap_or("index");
+                    collect(
+                        schemas,
+                        items,
+                        &format!("{template}[{{{identity}}}]"),
+                        descriptors,
+                    );
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Follows `$ref`s, including the `allOf` wrapper used for optional structs.
+fn resolve<'a>(schemas: &'a Schemas, schema: &'a RefOr<Schema>) -> Option<&'a Schema> {
+    match schema {
+        RefOr::Ref(reference) => {
+            let name = reference
+                .ref_location
+                .strip_prefix("#/components/schemas/")?;
+            resolve(schemas, schemas.get(name)?)
+        }
+        RefOr::T(Schema::AllOf(all)) => resolve(schemas, all.items.first()?),
+        RefOr::T(schema) => Some(schema),
+    }
+}
+
+fn descriptor(template: String, field: &str, schema: &Object) -> MetricDescriptor {
+    // `_total` marks a running count; `mem_total_mb` and friends are capacities.
+    let counter =
+        field.ends_with("_total") || field.ends_with("_total_bytes") || COUNTERS.contains(&field);
+    let description = schema
+        .description
+        .as_deref()
+        .unwrap_or_default()
+        .trim()
+        .trim_end_matches('.')
+        .to_string();
+    MetricDescriptor {
+        template,
+        kind: if counter {
+            MetricKind::Counter
+        } else {
+            MetricKind::Gauge
+        },
+        unit: unit(field, &schema.schema_type),
+        description,
+    }
+}
+
+fn unit(field: &str, kind: &SchemaType) -> &'static str {
+    if field.starts_with("load_avg") {
+        return "load";
+    }
+    let name = field.strip_suffix("_total").unwrap_or(field);
+    match UNITS.iter().find(|(suffix, _)| name.ends_with(suffix)) {
+        Some((_, unit)) => unit,
+        // Integers without a unit suffix count something.
+        None if matches!(kind, SchemaType::Integer) => "count",
+        None => "",
+    }
+}
+
+fn entry(descriptor: &MetricDescriptor, path: String, instance: Option<String>) -> CatalogEntry {
+    CatalogEntry {
+        path,
+        template: descriptor.template.clone(),
+        kind: descriptor.kind,
+        unit: descriptor.unit,
+        description: descriptor.description.clone(),
+        instance,
+    }
+}
+
+/// Splits `disks[{mount_point}].used_pct` into `("disks[", "mount_point", "].used_pct")`.
+fn split_template(template: &str) -> Option<(&str, &str, &str)> {
+    let (prefix, rest) = template.split_once('{')?;
+    let (placeholder, suffix) = rest.split_once('}')?;
+    Some((prefix, placeholder, suffix))
+}
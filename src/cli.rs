@@ -0,0 +1,442 @@
+//! Command-line interface. Everything but `run` works without the HTTP server.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use flate2::read::GzDecoder;
+
+use crate::{
+    catalog,
+    collector::{self, SystemSnapshot},
+    config::Config,
+    keys,
+    series::MetricPath,
+    storage::{self, SnapshotHistory},
+    tls,
+};
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "agent",
+    version,
+    about = "System monitoring agent with JSON API, dashboard and Grafana datasource"
+)]
+pub struct Cli {
+    /// TOML configuration file; environment variables override its values.
+    #[arg(long, global = true, env = "CONFIG_FILE", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the collector and the HTTP server (default).
+    Run,
+    /// Collect one snapshot and print it.
+    Snapshot {
+        #[arg(long, value_enum, default_value_t = SnapshotFormat::Table)]
+        format: SnapshotFormat,
+    },
+    /// Validate the configuration, key file and TLS certificates.
+    CheckConfig {
+        /// Defaults to --config.
+        path: Option<PathBuf>,
+    },
+    /// Write stored snapshots in a time range to JSON or CSV.
+    Export(ExportArgs),
+    /// Add snapshots from an export (JSON, JSON lines or .jsonl.gz archive) to storage.
+    Import { file: PathBuf },
+    /// Print the version.
+    Version,
+    /// Print the salted hash of a key for SYSTEM_API_KEY, UI_PASSWORD or the key file.
+    HashKey {
+        /// Read from stdin when omitted; empty input generates a new random key.
+        key: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SnapshotFormat {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Start, Unix milliseconds or RFC 3339 (e.g. 2024-05-01T00:00:00Z).
+    #[arg(long, value_parser = parse_time)]
+    from: Option<i64>,
+    /// End, Unix milliseconds or RFC 3339.
+    #[arg(long, value_parser = parse_time)]
+    to: Option<i64>,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+    format: ExportFormat,
+    /// CSV columns as metric paths, comma-separated; defaults to every metric
+    /// in the catalog.
+    #[arg(long, value_delimiter = ',')]
+    metrics: Vec<String>,
+    /// Output file instead of stdout.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+/// `agent snapshot` collects like the scheduler does, with the configured
+/// collector settings but without a previous snapshot, so network and disk I/O
+/// rates are 0.
+pub async fn snapshot(config_file: Option<&Path>, format: SnapshotFormat) -> CliResult {
+    let config = Config::load_local(config_file)?;
+    let interval = config.collection_interval();
+    let settings = config.collector().clone();
+    let snapshot =
+        tokio::task::spawn_blocking(move || collector::collect_snapshot(None, interval, &settings))
+            .await?;
+
+    match format {
+        SnapshotFormat::Json => println!("{}", serde_json::to_string_pretty(&snapshot)?),
+        SnapshotFormat::Table => print_table(&snapshot),
+    }
+    Ok(())
+}
+
+/// `agent check-config [PATH]` validates the configuration (file, environment,
+/// key file and TLS certificates) without starting the server. Every problem
+/// found is reported, not just the first.
+pub fn check_config(file: Option<PathBuf>) -> CliResult {
+    let config = Config::load(file.as_deref()).map_err(|err| Problems(vec![err.to_string()]))?;
+    let mut problems = Vec::new();
+    if let Err(err) = keys::KeyStore::open(config.api_keys_file()) {
+        problems.push(err.to_string());
+    }
+    if let Some(tls) = config.tls() {
+        if let Err(err) = tls::server_config(tls) {
+            problems.push(err.to_string());
+        }
+    }
+    if !problems.is_empty() {
+        return Err(Problems(problems).into());
+    }
+
+    let source = file.map_or("environment".to_string(), |path| path.display().to_string());
+    println!("✓ {source}: configuration is valid");
+    println!("{}", serde_json::to_string_pretty(&config.summary())?);
+    Ok(())
+}
+
+/// Problems found by `check-config`, one per line.
+#[derive(Debug)]
+struct Problems(Vec<String>);
+
+impl fmt::Display for Problems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `main` marks the first line; the others get the same mark.
+        f.write_str(&self.0.join("\n✗ "))
+    }
+}
+
+impl Error for Problems {}
+
+pub async fn export(config_file: Option<&Path>, args: ExportArgs) -> CliResult {
+    let config = Config::load_local(config_file)?;
+    let snapshots = storage::load_range(&config, args.from, args.to).await?;
+    let count = snapshots.len();
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    match args.format {
+        ExportFormat::Json => {
+            serde_json::to_writer(&mut out, &SnapshotHistory { snapshots })?;
+            writeln!(out)?;
+        }
+        ExportFormat::Csv => write_csv(&mut out, &snapshots, &args.metrics)?,
+    }
+    out.flush()?;
+
+    if let Some(path) = &args.output {
+        eprintln!("{count} snapshot(s) written to {}", path.display());
+    }
+    Ok(())
+}
+
+pub async fn import(config_file: Option<&Path>, file: &Path) -> CliResult {
+    let config = Config::load_local(config_file)?;
+    // The server compacts and prunes the same directory; never write next to it.
+    let lock = storage::lock(&config)?;
+    let snapshots = read_snapshots(file)?;
+    let added = storage::import_snapshots(&snapshots, &config, &lock).await?;
+    eprintln!(
+        "{added} of {} snapshot(s) imported into {}; the others were already stored",
+        snapshots.len(),
+        config.snapshot_dir().display()
+    );
+    Ok(())
+}
+
+pub fn version() {
+    println!("agent {}", env!("CARGO_PKG_VERSION"));
+}
+
+/// `agent hash-key [KEY]` prints the salted hash to put into `SYSTEM_API_KEY`,
+/// `UI_PASSWORD` or the key file. The key is read from stdin when omitted; empty
+/// input generates a new random key.
+pub fn hash_key(key: Option<String>) -> CliResult {
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            line.trim().to_string()
+        }
+    };
+
+    if key.is_empty() {
+        let key = keys::generate_key();
+        println!("key:  {key}");
+        println!("hash: {}", keys::Secret::hash(&key));
+    } else {
+        println!("{}", keys::Secret::hash(&key));
+    }
+    Ok(())
+}
+
+fn parse_time(value: &str) -> Result<i64, String> {
+    if let Ok(millis) = value.parse::<i64>() {
+        return Ok(millis);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp_millis())
+        .map_err(|_| format!("`{value}` is neither Unix milliseconds nor an RFC 3339 time"))
+}
+
+/// One row per snapshot with `timestamp` (Unix ms), `time` (RFC 3339) and a
+/// column per metric path.
+fn write_csv(out: &mut dyn Write, snapshots: &[SystemSnapshot], metrics: &[String]) -> CliResult {
+    let columns: Vec<MetricPath> = if metrics.is_empty() {
+        let mut paths: Vec<String> = Vec::new();
+        for snapshot in snapshots {
+            for entry in catalog::catalog(snapshot) {
+                if !paths.contains(&entry.path) {
+                    paths.push(entry.path);
+                }
+            }
+        }
+        paths
+            .iter()
+            .map(|path| path.parse())
+            .collect::<Result<_, _>>()?
+    } else {
+        metrics
+            .iter()
+            .map(|path| path.parse())
+            .collect::<Result<_, _>>()?
+    };
+
+    let header: Vec<String> = ["timestamp", "time"]
+        .into_iter()
+        .map(str::to_string)
+        .chain(columns.iter().map(|column| csv_field(column.as_str())))
+        .collect();
+    writeln!(out, "{}", header.join(","))?;
+
+    for snapshot in snapshots {
+        let value = serde_json::to_value(snapshot)?;
+        let time = Utc
+            .timestamp_millis_opt(snapshot.timestamp)
+            .single()
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_default();
+        let mut row = vec![snapshot.timestamp.to_string(), time];
+        row.extend(
+            columns
+                .iter()
+                .map(|column| match column.resolve_json(&value) {
+                    Some(serde_json::Value::String(text)) => csv_field(text),
+                    Some(serde_json::Value::Null) | None => String::new(),
+                    Some(other) => csv_field(&other.to_string()),
+                }),
+        );
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reads `{"snapshots": [...]}` (the export and `/api/snapshots` format), a
+/// plain array, or JSON lines as in the archives, optionally gzip-compressed.
+fn read_snapshots(path: &Path) -> Result<Vec<SystemSnapshot>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let mut reader: Box<dyn BufRead> = if path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(BufReader::new(GzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    if let Ok(history) = serde_json::from_str::<SnapshotHistory>(&content) {
+        return Ok(history.snapshots);
+    }
+    if let Ok(snapshots) = serde_json::from_str::<Vec<SystemSnapshot>>(&content) {
+        return Ok(snapshots);
+    }
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|err| format!("{}:{}: {err}", path.display(), index + 1).into())
+        })
+        .collect()
+}
+
+fn print_table(snapshot: &SystemSnapshot) {
+    let time = Utc
+        .timestamp_millis_opt(snapshot.timestamp)
+        .single()
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default();
+    println!(
+        "{} · {} · up {}",
+        snapshot.hostname.as_deref().unwrap_or("unknown host"),
+        time,
+        format_uptime(snapshot.uptime_seconds)
+    );
+    println!();
+
+    let load = [
+        snapshot.load_avg_one,
+        snapshot.load_avg_five,
+        snapshot.load_avg_fifteen,
+    ]
+    .map(|value| value.map_or("-".to_string(), |v| format!("{v:.2}")))
+    .join(" ");
+    println!(
+        "CPU     {:>6.1} %   {} cores, load {}",
+        snapshot.cpu_usage_pct, snapshot.cpu_logical_cores, load
+    );
+    println!(
+        "Memory  {:>6.1} %   {} / {} MB",
+        percent(snapshot.mem_used_mb, snapshot.mem_total_mb),
+        snapshot.mem_used_mb,
+        snapshot.mem_total_mb
+    );
+    println!(
+        "Swap    {:>6.1} %   {} / {} MB",
+        percent(snapshot.swap_used_mb, snapshot.swap_total_mb),
+        snapshot.swap_used_mb,
+        snapshot.swap_total_mb
+    );
+    for gpu in &snapshot.gpus {
+        println!(
+            "GPU {}   {:>6} %   {}",
+            gpu.index,
+            gpu.gpu_usage_pct
+                .map_or("-".to_string(), |v| format!("{v:.1}")),
+            gpu.name
+        );
+    }
+
+    if !snapshot.disks.is_empty() {
+        println!();
+        println!(
+            "{:<16} {:>10} {:>10} {:>7}",
+            "MOUNT", "USED GB", "TOTAL GB", "USED %"
+        );
+        for disk in &snapshot.disks {
+            println!(
+                "{:<16} {:>10.1} {:>10.1} {:>7.1}",
+                disk.mount_point, disk.used_gb, disk.total_gb, disk.used_pct
+            );
+        }
+    }
+
+    if !snapshot.network.is_empty() {
+        println!();
+        println!(
+            "{:<16} {:>14} {:>14}",
+            "INTERFACE", "RECEIVED MB", "SENT MB"
+        );
+        for iface in &snapshot.network {
+            println!(
+                "{:<16} {:>14.1} {:>14.1}",
+                iface.name,
+                iface.received_total_bytes as f64 / 1_048_576.0,
+                iface.transmitted_total_bytes as f64 / 1_048_576.0
+            );
+        }
+    }
+
+    if !snapshot.top_processes.is_empty() {
+        println!();
+        println!(
+            "{:>8} {:<24} {:>7} {:>10}",
+            "PID", "PROCESS", "CPU %", "MEMORY MB"
+        );
+        for process in snapshot.top_processes.iter().take(10) {
+            println!(
+                "{:>8} {:<24} {:>7.1} {:>10}",
+                process.pid,
+                truncate(&process.name, 24),
+                process.cpu_pct,
+                process.memory_mb
+            );
+        }
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (
+        seconds / 86_400,
+        seconds % 86_400 / 3600,
+        seconds % 3600 / 60,
+    );
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else {
+        format!("{hours}h {minutes}m")
+    }
+}
+
+fn truncate(value: &str, width: usize) -> String {
+    if value.chars().count() <= width {
+        value.to_string()
+    } else {
+        let mut short: String = value.chars().take(width - 1).collect();
+        short.push('…');
+        short
+    }
+}
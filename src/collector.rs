@@ -1,83 +1,179 @@
-use std::{collections::HashMap, process::Command, thread, time::Duration};
+use std::{
+    collections::HashMap,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
 
 use chrono::Utc;
-use nvml_wrapper::{enum_wrappers::device::TemperatureSensor, Nvml};
+use nvml_wrapper::{enum_wrappers::device::TemperatureSensor, error::NvmlError, Nvml};
 use serde::{Deserialize, Serialize};
-use sysinfo::{Disks, Networks, ProcessStatus, System};
+use sysinfo::{Disks, Networks, Pid, ProcessRefreshKind, ProcessStatus, System, UpdateKind};
+use utoipa::ToSchema;
 
-const TOP_PROCESS_LIMIT: usize = 25;
+use crate::config::CollectorConfig;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Pause between the two refreshes CPU and process usage are computed from.
+const SAMPLING_WAIT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(default)]
 pub struct SystemSnapshot {
     pub timestamp: i64,
     pub hostname: Option<String>,
+    /// Host uptime
     pub uptime_seconds: u64,
+    /// Overall CPU usage
     pub cpu_usage_pct: f64,
+    /// Load average over 1 minute
     pub load_avg_one: Option<f64>,
+    /// Load average over 5 minutes
     pub load_avg_five: Option<f64>,
+    /// Load average over 15 minutes
     pub load_avg_fifteen: Option<f64>,
+    /// Used memory
     pub mem_used_mb: u64,
+    /// Total memory
     pub mem_total_mb: u64,
+    /// Available memory
     pub mem_available_mb: u64,
     pub disks: Vec<DiskUsage>,
     pub network: Vec<NetworkInterfaceUsage>,
     pub top_processes: Vec<ProcessInfo>,
+    /// Average utilisation across all GPUs
     pub gpu_usage_pct: Option<f64>,
+    /// Average memory usage across all GPUs
     pub gpu_memory_usage_pct: Option<f64>,
     pub gpus: Vec<GpuInfo>,
+    /// CPU usage of one logical core
     pub cpu_per_core_usage_pct: Vec<f64>,
+    /// Number of logical CPU cores
     pub cpu_logical_cores: usize,
+    /// Number of physical CPU cores
     pub cpu_physical_cores: Option<usize>,
+    /// Total swap
     pub swap_total_mb: u64,
+    /// Used swap
     pub swap_used_mb: u64,
+    /// Free swap
     pub swap_free_mb: u64,
+    /// The agent's own cost; attached by the scheduler, absent in older snapshots.
+    pub agent: Option<AgentMetrics>,
+}
+
+/// How long the collection behind a snapshot took and what the agent uses.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct AgentMetrics {
+    /// Duration of the last collection
+    pub collection_ms: f64,
+    pub sections: Vec<SectionMetrics>,
+    /// Latency of the previous snapshot write.
+    pub storage_write_ms: Option<f64>,
+    /// Collections that took longer than the collection interval, since start.
+    pub overruns: u64,
+    /// CPU usage of the agent
+    pub cpu_pct: Option<f64>,
+    /// Resident memory of the agent
+    pub rss_mb: Option<u64>,
+}
+
+/// One part of a collection (`cpu`, `processes`, `disks`, `network`, `nvml`,
+/// `nvidia-smi`, `sampling_wait`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct SectionMetrics {
+    pub name: String,
+    /// Duration of this section
+    pub duration_ms: f64,
+    /// Failures of this section since start.
+    pub failures: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct DiskUsage {
     pub name: String,
     pub mount_point: String,
     pub filesystem: String,
+    /// Disk capacity
     pub total_gb: f64,
+    /// Used disk space
     pub used_gb: f64,
+    /// Used disk space as a percentage of capacity
     pub used_pct: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct NetworkInterfaceUsage {
     pub name: String,
+    /// Bytes received since boot
     pub received_total_bytes: u64,
+    /// Bytes sent since boot
     pub transmitted_total_bytes: u64,
+    /// Receive rate
     pub received_kbps: f64,
+    /// Transmit rate
     pub transmitted_kbps: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ProcessInfo {
     pub pid: i64,
     pub name: String,
+    /// Process CPU usage
     pub cpu_pct: f64,
+    /// Process resident memory
     pub memory_mb: u64,
+    /// Process share of total memory
     pub memory_pct: f64,
+    /// Process virtual memory
     pub virtual_memory_mb: u64,
     pub status: Option<String>,
+    /// Bytes read by the process
     pub disk_read_bytes_total: u64,
+    /// Bytes written by the process
     pub disk_write_bytes_total: u64,
+    /// Process disk read rate
     pub disk_read_kbps: f64,
+    /// Process disk write rate
     pub disk_write_kbps: f64,
+    /// Process thread count
     pub thread_count: Option<usize>,
+    pub parent_pid: Option<i64>,
+}
+
+/// Live details about one process that are too large to keep in every snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProcessDetails {
+    pub pid: i64,
+    pub name: String,
+    pub parent: Option<ProcessRef>,
+    pub children: Vec<ProcessRef>,
+    pub command: Vec<String>,
+    pub executable: Option<String>,
+    pub working_directory: Option<String>,
+    pub started_at: i64,
+    pub run_time_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProcessRef {
+    pub pid: i64,
+    pub name: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct GpuInfo {
     pub index: u32,
     pub name: String,
     pub uuid: Option<String>,
+    /// GPU utilisation
     pub gpu_usage_pct: Option<f64>,
+    /// Used GPU memory
     pub memory_used_mb: Option<u64>,
+    /// Total GPU memory
     pub memory_total_mb: Option<u64>,
+    /// GPU memory in use as a percentage of total
     pub memory_usage_pct: Option<f64>,
+    /// GPU temperature
     pub temperature_celsius: Option<f64>,
 }
 
@@ -106,23 +202,85 @@ impl Default for SystemSnapshot {
             swap_total_mb: 0,
             swap_used_mb: 0,
             swap_free_mb: 0,
+            agent: None,
         }
     }
 }
 
-pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) -> SystemSnapshot {
-    let mut system = System::new_all();
-    let mut networks = Networks::new_with_refreshed_list();
-    let disks = Disks::new_with_refreshed_list();
+/// A data source that failed during one collection, e.g. `nvml`. Sources that
+/// are simply not present (no NVIDIA driver) are not errors.
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceError {
+    pub source: &'static str,
+    pub message: String,
+}
 
-    system.refresh_all();
+/// Side information about one collection.
+#[derive(Debug, Clone, Default)]
+pub struct CollectionReport {
+    pub errors: Vec<SourceError>,
+    /// Time spent per section, in collection order.
+    pub timings: Vec<(&'static str, Duration)>,
+    /// CPU usage and resident memory of the agent process.
+    pub agent_cpu_pct: Option<f64>,
+    pub agent_rss_mb: Option<u64>,
+}
 
-    // Refresh twice for more accurate CPU/process usage figures.
-    thread::sleep(Duration::from_millis(200));
+impl CollectionReport {
+    /// Adds the time since `started` to `section`.
+    fn time(&mut self, section: &'static str, started: Instant) {
+        let elapsed = started.elapsed();
+        match self.timings.iter_mut().find(|(name, _)| *name == section) {
+            Some((_, total)) => *total += elapsed,
+            None => self.timings.push((section, elapsed)),
+        }
+    }
+
+    fn error(&mut self, source: &'static str, message: impl ToString) {
+        self.errors.push(SourceError {
+            source,
+            message: message.to_string(),
+        });
+    }
+}
+
+pub fn collect_snapshot(
+    previous: Option<SystemSnapshot>,
+    interval: Duration,
+    settings: &CollectorConfig,
+) -> SystemSnapshot {
+    collect_snapshot_with_report(previous, interval, settings).0
+}
+
+/// Collects a snapshot and reports which sources failed along the way.
+pub fn collect_snapshot_with_report(
+    previous: Option<SystemSnapshot>,
+    interval: Duration,
+    settings: &CollectorConfig,
+) -> (SystemSnapshot, CollectionReport) {
+    let mut report = CollectionReport::default();
+    let mut system = System::new();
+
+    let started = Instant::now();
     system.refresh_cpu();
+    system.refresh_memory();
+    report.time("cpu", started);
+
+    let started = Instant::now();
     system.refresh_processes();
-    networks.refresh();
+    report.time("processes", started);
 
+    let started = Instant::now();
+    let mut networks = Networks::new_with_refreshed_list();
+    report.time("network", started);
+
+    // Refresh twice for more accurate CPU/process usage figures.
+    let started = Instant::now();
+    thread::sleep(SAMPLING_WAIT);
+    report.time("sampling_wait", started);
+
+    let started = Instant::now();
+    system.refresh_cpu();
     let cpu_usage_pct = system.global_cpu_info().cpu_usage() as f64;
     let cpu_per_core_usage_pct: Vec<f64> = system
         .cpus()
@@ -145,18 +303,13 @@ pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) ->
     let swap_total_mb = kib_to_mb(swap_total_kib);
     let swap_used_mb = kib_to_mb(swap_used_kib);
     let swap_free_mb = swap_total_mb.saturating_sub(swap_used_mb);
+    report.time("cpu", started);
 
+    let started = Instant::now();
+    let disks = Disks::new_with_refreshed_list();
     let disk_usage: Vec<DiskUsage> = disks
         .iter()
-        .filter(|disk| {
-            let mount_point = disk.mount_point().to_string_lossy().to_string();
-
-            // Sadece ana mount point'leri tut (whitelist yaklaşımı)
-            mount_point == "/"
-                || mount_point == "/home"
-                || mount_point == "/mnt/c"
-                || mount_point == "/mnt/d"
-        })
+        .filter(|disk| settings.keeps_mount_point(&disk.mount_point().to_string_lossy()))
         .map(|disk| {
             let total_gb = bytes_to_gb(disk.total_space());
             let available_gb = bytes_to_gb(disk.available_space());
@@ -176,7 +329,10 @@ pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) ->
             }
         })
         .collect();
+    report.time("disks", started);
 
+    let started = Instant::now();
+    networks.refresh();
     let previous_network_totals: HashMap<String, (u64, u64)> = previous
         .as_ref()
         .map(|snap| {
@@ -195,6 +351,7 @@ pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) ->
     let seconds = interval.as_secs_f64().max(0.1);
     let network_usage: Vec<NetworkInterfaceUsage> = networks
         .iter()
+        .filter(|(name, _)| settings.keeps_interface(name))
         .map(|(name, data)| {
             let received_total = data.total_received();
             let transmitted_total = data.total_transmitted();
@@ -220,6 +377,7 @@ pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) ->
             }
         })
         .collect();
+    report.time("network", started);
 
     let previous_process_totals: HashMap<i64, (u64, u64)> = previous
         .as_ref()
@@ -236,11 +394,25 @@ pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) ->
         })
         .unwrap_or_default();
 
-    let (gpus, gpu_usage_pct, gpu_memory_usage_pct) = collect_gpu_info();
+    let (gpus, gpu_usage_pct, gpu_memory_usage_pct) = if settings.gpu {
+        collect_gpu_info(&mut report)
+    } else {
+        (Vec::new(), None, None)
+    };
 
+    let started = Instant::now();
+    system.refresh_processes();
+    if let Some(agent) = sysinfo::get_current_pid()
+        .ok()
+        .and_then(|pid| system.process(pid))
+    {
+        report.agent_cpu_pct = Some(agent.cpu_usage() as f64);
+        report.agent_rss_mb = Some(bytes_to_mb(agent.memory()));
+    }
     let mut top_processes: Vec<ProcessInfo> = system
         .processes()
         .iter()
+        .filter(|(_, process)| settings.keeps_process(process.name()))
         .map(|(pid, process)| {
             let pid_i64 = pid.as_u32() as i64;
             let disk_usage = process.disk_usage();
@@ -266,6 +438,7 @@ pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) ->
                 disk_read_kbps: bytes_per_second_to_kbps(read_delta, seconds),
                 disk_write_kbps: bytes_per_second_to_kbps(write_delta, seconds),
                 thread_count: process.tasks().map(|tasks| tasks.len()),
+                parent_pid: process.parent().map(|parent| parent.as_u32() as i64),
             }
         })
         .collect();
@@ -275,9 +448,10 @@ pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) ->
             .partial_cmp(&a.cpu_pct)
             .unwrap_or(std::cmp::Ordering::Equal)
     });
-    top_processes.truncate(TOP_PROCESS_LIMIT);
+    top_processes.truncate(settings.top_processes);
+    report.time("processes", started);
 
-    SystemSnapshot {
+    let snapshot = SystemSnapshot {
         timestamp: Utc::now().timestamp_millis(),
         hostname: System::host_name(),
         uptime_seconds: System::uptime(),
@@ -300,7 +474,59 @@ pub fn collect_snapshot(previous: Option<SystemSnapshot>, interval: Duration) ->
         swap_total_mb,
         swap_used_mb,
         swap_free_mb,
-    }
+        agent: None,
+    };
+    (snapshot, report)
+}
+
+/// Looks up a running process, including its command line and direct children.
+/// Returns `None` when the process no longer exists.
+pub fn inspect_process(pid: i64) -> Option<ProcessDetails> {
+    let target = Pid::from_u32(u32::try_from(pid).ok()?);
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessRefreshKind::new()
+            .with_cmd(UpdateKind::Always)
+            .with_exe(UpdateKind::Always)
+            .with_cwd(UpdateKind::Always),
+    );
+
+    let process = system.process(target)?;
+    let process_ref = |pid: Pid| {
+        system.process(pid).map(|p| ProcessRef {
+            pid: pid.as_u32() as i64,
+            name: p.name().to_string(),
+        })
+    };
+
+    let threads = process.tasks();
+    let mut children: Vec<ProcessRef> = system
+        .processes()
+        .iter()
+        .filter(|(child, candidate)| {
+            // Threads are listed as processes too; keep real children only.
+            candidate.parent() == Some(target)
+                && !threads.is_some_and(|tasks| tasks.contains(child))
+        })
+        .filter_map(|(child, _)| process_ref(*child))
+        .collect();
+    children.sort_by_key(|child| child.pid);
+
+    Some(ProcessDetails {
+        pid,
+        name: process.name().to_string(),
+        parent: process.parent().and_then(process_ref),
+        children,
+        command: process.cmd().to_vec(),
+        executable: process
+            .exe()
+            .map(|path| path.to_string_lossy().into_owned()),
+        working_directory: process
+            .cwd()
+            .map(|path| path.to_string_lossy().into_owned()),
+        started_at: (process.start_time() as i64) * 1000,
+        run_time_seconds: process.run_time(),
+    })
 }
 
 fn kib_to_mb(value: u64) -> u64 {
@@ -355,27 +581,45 @@ fn bytes_to_mb(value: u64) -> u64 {
     (value as f64 / (1024.0 * 1024.0)).round() as u64
 }
 
-fn collect_gpu_info() -> (Vec<GpuInfo>, Option<f64>, Option<f64>) {
-    if let Some(result) = collect_gpu_info_nvml() {
+fn collect_gpu_info(report: &mut CollectionReport) -> (Vec<GpuInfo>, Option<f64>, Option<f64>) {
+    let started = Instant::now();
+    let nvml = collect_gpu_info_nvml(report);
+    report.time("nvml", started);
+    if let Some(result) = nvml {
         return result;
     }
 
-    if let Some(result) = collect_gpu_info_nvidia_smi() {
+    let started = Instant::now();
+    let nvidia_smi = collect_gpu_info_nvidia_smi(report);
+    report.time("nvidia-smi", started);
+    if let Some(result) = nvidia_smi {
         return result;
     }
 
     (Vec::new(), None, None)
 }
 
-fn collect_gpu_info_nvml() -> Option<(Vec<GpuInfo>, Option<f64>, Option<f64>)> {
+fn collect_gpu_info_nvml(
+    report: &mut CollectionReport,
+) -> Option<(Vec<GpuInfo>, Option<f64>, Option<f64>)> {
     let nvml = match Nvml::init() {
         Ok(nvml) => nvml,
-        Err(_) => return None,
+        // No NVIDIA driver installed: nothing to report.
+        Err(
+            NvmlError::LibloadingError(_) | NvmlError::LibraryNotFound | NvmlError::DriverNotLoaded,
+        ) => return None,
+        Err(err) => {
+            report.error("nvml", err);
+            return None;
+        }
     };
 
     let count = match nvml.device_count() {
         Ok(count) => count,
-        Err(_) => return None,
+        Err(err) => {
+            report.error("nvml", err);
+            return None;
+        }
     };
 
     let mut details = Vec::new();
@@ -387,7 +631,10 @@ fn collect_gpu_info_nvml() -> Option<(Vec<GpuInfo>, Option<f64>, Option<f64>)> {
     for index in 0..count {
         let device = match nvml.device_by_index(index) {
             Ok(device) => device,
-            Err(_) => continue,
+            Err(err) => {
+                report.error("nvml", format!("GPU {index}: {err}"));
+                continue;
+            }
         };
 
         let name = device.name().unwrap_or_else(|_| "N/A".to_string());
@@ -450,20 +697,41 @@ fn collect_gpu_info_nvml() -> Option<(Vec<GpuInfo>, Option<f64>, Option<f64>)> {
     Some((details, average_usage_pct, average_mem_usage_pct))
 }
 
-fn collect_gpu_info_nvidia_smi() -> Option<(Vec<GpuInfo>, Option<f64>, Option<f64>)> {
-    let output = Command::new("nvidia-smi")
+fn collect_gpu_info_nvidia_smi(
+    report: &mut CollectionReport,
+) -> Option<(Vec<GpuInfo>, Option<f64>, Option<f64>)> {
+    let output = match Command::new("nvidia-smi")
         .args([
             "--query-gpu=index,name,utilization.gpu,utilization.memory,memory.total,memory.used,temperature.gpu",
             "--format=csv,noheader,nounits",
         ])
         .output()
-        .ok()?;
+    {
+        Ok(output) => output,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(err) => {
+            report.error("nvidia-smi", err);
+            return None;
+        }
+    };
 
     if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().next().unwrap_or_default().trim();
+        report.error(
+            "nvidia-smi",
+            format!("exited with {}: {}", output.status, message),
+        );
         return None;
     }
 
-    let stdout = String::from_utf8(output.stdout).ok()?;
+    let stdout = match String::from_utf8(output.stdout) {
+        Ok(stdout) => stdout,
+        Err(err) => {
+            report.error("nvidia-smi", err);
+            return None;
+        }
+    };
     let mut details = Vec::new();
     let mut usage_total = 0.0;
     let mut usage_count = 0.0;
@@ -1,60 +1,556 @@
 use std::{
-    env,
+    collections::{BTreeMap, HashMap},
+    env, fs,
     path::{Path, PathBuf},
     time::Duration,
 };
 
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use toml::Spanned;
+use utoipa::ToSchema;
+
+use crate::keys::{ApiKey, Scope, Secret};
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    api_key: String,
+    api_key: Option<Secret>,
+    api_keys_file: Option<PathBuf>,
+    api_keys: Vec<ApiKey>,
     bind_address: String,
     snapshot_dir: PathBuf,
     history_limit: usize,
     collection_interval: Duration,
+    collector: CollectorConfig,
+    archive_window: Duration,
+    storage_budget_bytes: Option<u64>,
+    ui_password: Option<Secret>,
+    session_ttl: Duration,
+    rate_limits: RateLimits,
+    access: AccessRules,
+    audit: Option<AuditConfig>,
+    tls: Option<TlsConfig>,
+    tokens: TokenConfig,
+    ready_stale_intervals: u32,
+    shutdown_timeout: Duration,
+    config_file: Option<PathBuf>,
+}
+
+/// Configuration as reported by `/api/agent/status`, without any secrets.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConfigSummary {
+    pub config_file: Option<String>,
+    pub bind_address: String,
+    pub snapshot_dir: String,
+    pub history_limit: usize,
+    pub collection_interval_secs: f64,
+    pub top_processes: usize,
+    pub collect_gpu: bool,
+    pub disk_mount_points: Vec<String>,
+    pub network_exclude: Vec<String>,
+    pub process_exclude: Vec<String>,
+    pub archive_window_secs: u64,
+    pub storage_budget_mb: Option<u64>,
+    /// `hashed`, `plaintext` or `none`.
+    pub api_key: &'static str,
+    pub api_keys_file: Option<String>,
+    /// Number of `[[auth.keys]]` entries in the config file.
+    pub inline_api_keys: usize,
+    pub ui_password: bool,
+    pub session_ttl_secs: u64,
+    pub rate_limit_per_minute: u32,
+    pub history_rate_limit_per_minute: u32,
+    pub auth_failure_limit: u32,
+    pub access_allow: Vec<String>,
+    pub access_deny: Vec<String>,
+    pub trusted_proxies: Vec<String>,
+    pub audit_log: Option<String>,
+    pub tls: bool,
+    pub client_certificates: bool,
+    pub token_signing_key: bool,
+    pub api_keys_in_query: bool,
+    pub ready_stale_intervals: u32,
+    pub shutdown_timeout_secs: u64,
+}
+
+/// What the collector gathers. Filter entries match a name exactly or, when
+/// they end in `*`, by prefix; a lone `*` matches everything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectorConfig {
+    /// Size of the process list; 0 leaves it empty.
+    pub top_processes: usize,
+    pub gpu: bool,
+    /// Mount points of the disks to report.
+    pub mount_points: Vec<String>,
+    /// Network interfaces to leave out.
+    pub exclude_interfaces: Vec<String>,
+    /// Process names to leave out of the process list.
+    pub exclude_processes: Vec<String>,
+}
+
+impl Default for CollectorConfig {
+    fn default() -> Self {
+        Self {
+            top_processes: 25,
+            gpu: true,
+            mount_points: ["/", "/home", "/mnt/c", "/mnt/d"]
+                .map(String::from)
+                .to_vec(),
+            exclude_interfaces: Vec::new(),
+            exclude_processes: Vec::new(),
+        }
+    }
+}
+
+impl CollectorConfig {
+    pub fn keeps_mount_point(&self, mount_point: &str) -> bool {
+        matches_any(&self.mount_points, mount_point)
+    }
+
+    pub fn keeps_interface(&self, name: &str) -> bool {
+        !matches_any(&self.exclude_interfaces, name)
+    }
+
+    pub fn keeps_process(&self, name: &str) -> bool {
+        !matches_any(&self.exclude_processes, name)
+    }
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        })
+}
+
+/// Short-lived signed tokens issued by `/api/tokens`.
+#[derive(Clone)]
+pub struct TokenConfig {
+    /// HMAC key. Without one a random key is generated at startup, so issued
+    /// tokens stop working after a restart.
+    pub signing_key: Option<Vec<u8>>,
+    pub default_ttl: Duration,
+    pub max_ttl: Duration,
+    /// Whether long-lived API keys are still accepted as query parameters.
+    pub keys_in_query: bool,
+}
+
+// The signing key must never end up in logs.
+impl std::fmt::Debug for TokenConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenConfig")
+            .field(
+                "signing_key",
+                &self.signing_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field("default_ttl", &self.default_ttl)
+            .field("max_ttl", &self.max_ttl)
+            .field("keys_in_query", &self.keys_in_query)
+            .finish()
+    }
+}
+
+/// HTTPS settings. With `client_ca` set, client certificates signed by that
+/// CA authenticate with `client_scopes` as an alternative to API keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+    pub client_cert_required: bool,
+    pub client_scopes: Vec<Scope>,
+}
+
+/// Location and rotation of the request audit log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditConfig {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    pub max_files: usize,
+}
+
+/// Source address filtering. Deny entries win over allow entries; an empty
+/// allow list admits every address that is not denied.
+#[derive(Debug, Clone)]
+pub struct AccessRules {
+    pub allow: Vec<IpNet>,
+    pub deny: Vec<IpNet>,
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+/// Request limits applied per client IP and to the history endpoints. A limit
+/// of 0 disables the respective check.
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    pub per_client_per_minute: u32,
+    pub history_per_minute: u32,
+    pub auth_failure_limit: u32,
+    pub lockout: Duration,
+    pub exempt: Vec<IpNet>,
 }
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
-    #[error("SYSTEM_API_KEY is missing - set it in the environment or .env file")]
+    #[error(
+        "SYSTEM_API_KEY or API_KEYS_FILE is missing - set one in the environment, .env file or the [auth] section of the config file, or add [[auth.keys]] entries"
+    )]
     MissingApiKey,
+    #[error("cannot read config file {0}: {1}")]
+    ReadFile(String, std::io::Error),
+    #[error("{0}: {1}")]
+    ParseFile(String, toml::de::Error),
+    #[error("{path}:{line}: `{key}`: {message}")]
+    FileEntry {
+        path: String,
+        line: usize,
+        key: String,
+        message: String,
+    },
+    #[error("invalid {0} value: {1}")]
+    InvalidSecret(&'static str, crate::keys::SecretError),
     #[error("invalid COLLECTION_INTERVAL_SECS value: {0}")]
     InvalidInterval(String),
+    #[error("invalid {0} value: {1}")]
+    InvalidCollector(&'static str, String),
     #[error("invalid HISTORY_LIMIT value: {0}")]
     InvalidHistory(String),
+    #[error("invalid ARCHIVE_WINDOW_SECS value: {0}")]
+    InvalidArchiveWindow(String),
+    #[error("invalid STORAGE_MAX_MB value: {0}")]
+    InvalidStorageBudget(String),
+    #[error("invalid SESSION_TTL_SECS value: {0}")]
+    InvalidSessionTtl(String),
+    #[error("invalid {0} value: {1}")]
+    InvalidRateLimit(&'static str, String),
+    #[error("invalid CIDR in {0}: {1}")]
+    InvalidCidr(&'static str, String),
+    #[error("invalid {0} value: {1}")]
+    InvalidAudit(&'static str, String),
+    #[error("TLS_CERT and TLS_KEY must be set together")]
+    IncompleteTls,
+    #[error("invalid {0} value: {1}")]
+    InvalidTls(&'static str, String),
+    #[error("invalid {0} value: {1}")]
+    InvalidToken(&'static str, String),
+    #[error("invalid READY_STALE_INTERVALS value: {0}")]
+    InvalidReadiness(String),
+    #[error("invalid SHUTDOWN_TIMEOUT_SECS value: {0}")]
+    InvalidShutdownTimeout(String),
+}
+
+impl ConfigError {
+    /// The environment variable whose value was rejected.
+    fn variable(&self) -> Option<&'static str> {
+        match self {
+            ConfigError::InvalidSecret(name, _)
+            | ConfigError::InvalidCollector(name, _)
+            | ConfigError::InvalidRateLimit(name, _)
+            | ConfigError::InvalidCidr(name, _)
+            | ConfigError::InvalidAudit(name, _)
+            | ConfigError::InvalidTls(name, _)
+            | ConfigError::InvalidToken(name, _) => Some(name),
+            ConfigError::InvalidInterval(_) => Some("COLLECTION_INTERVAL_SECS"),
+            ConfigError::InvalidHistory(_) => Some("HISTORY_LIMIT"),
+            ConfigError::InvalidArchiveWindow(_) => Some("ARCHIVE_WINDOW_SECS"),
+            ConfigError::InvalidStorageBudget(_) => Some("STORAGE_MAX_MB"),
+            ConfigError::InvalidSessionTtl(_) => Some("SESSION_TTL_SECS"),
+            ConfigError::InvalidReadiness(_) => Some("READY_STALE_INTERVALS"),
+            ConfigError::InvalidShutdownTimeout(_) => Some("SHUTDOWN_TIMEOUT_SECS"),
+            _ => None,
+        }
+    }
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self, ConfigError> {
-        let api_key = env::var("SYSTEM_API_KEY").map_err(|_| ConfigError::MissingApiKey)?;
-        let bind_address =
-            env::var("API_BIND_ADDRESS").unwrap_or_else(|_| "127.0.0.1:7000".to_string());
-        let snapshot_dir = env::var("SNAPSHOT_DIR")
+    /// Reads the configuration from `file` (TOML) if given, with environment
+    /// variables taking precedence over the file.
+    pub fn load(file: Option<&Path>) -> Result<Self, ConfigError> {
+        Self::load_with(file, true)
+    }
+
+    /// Like [`Config::load`], for commands that do not start the server and
+    /// so work without an API key.
+    pub fn load_local(file: Option<&Path>) -> Result<Self, ConfigError> {
+        Self::load_with(file, false)
+    }
+
+    /// Loads `file` alone, ignoring the environment, so tests do not depend on
+    /// variables exported in the developer's shell.
+    #[cfg(test)]
+    pub fn load_isolated(file: &Path) -> Result<Self, ConfigError> {
+        let source = Source {
+            ignore_environment: true,
+            ..Source::file(file)?
+        };
+        let mut config = Self::from_source(&source, true).map_err(|err| source.locate(err))?;
+        config.config_file = Some(file.to_path_buf());
+        Ok(config)
+    }
+
+    fn load_with(file: Option<&Path>, require_api_key: bool) -> Result<Self, ConfigError> {
+        let source = match file {
+            Some(path) => Source::file(path)?,
+            None => Source::default(),
+        };
+        let mut config =
+            Self::from_source(&source, require_api_key).map_err(|err| source.locate(err))?;
+        config.config_file = file.map(Path::to_path_buf);
+        Ok(config)
+    }
+
+    fn from_source(source: &Source, require_api_key: bool) -> Result<Self, ConfigError> {
+        let api_keys_file = source.path("API_KEYS_FILE");
+        // With named keys the legacy single key is optional; an empty key disables auth.
+        let api_key = match source.var("SYSTEM_API_KEY") {
+            Some(key) => parse_secret("SYSTEM_API_KEY", &key)?,
+            None if api_keys_file.is_some() || !source.keys.is_empty() || !require_api_key => None,
+            None => return Err(ConfigError::MissingApiKey),
+        };
+        let bind_address = source
+            .var("API_BIND_ADDRESS")
+            .unwrap_or_else(|| "127.0.0.1:7000".to_string());
+        let snapshot_dir = source
+            .var("SNAPSHOT_DIR")
             .map(PathBuf::from)
-            .unwrap_or_else(|_| PathBuf::from("data/snapshots"));
+            .unwrap_or_else(|| PathBuf::from("data/snapshots"));
 
-        let collection_interval =
-            env::var("COLLECTION_INTERVAL_SECS").unwrap_or_else(|_| "1".to_string());
+        let collection_interval = source
+            .var("COLLECTION_INTERVAL_SECS")
+            .unwrap_or_else(|| "1".to_string());
         let collection_interval_secs: f64 = collection_interval
             .parse()
             .map_err(|_| ConfigError::InvalidInterval(collection_interval.clone()))?;
-        let history_limit = env::var("HISTORY_LIMIT").unwrap_or_else(|_| "288".to_string());
+        let top_processes = source
+            .var("TOP_PROCESSES")
+            .unwrap_or_else(|| "25".to_string());
+        let gpu = source.var("COLLECT_GPU").unwrap_or_default();
+        let collector = CollectorConfig {
+            top_processes: top_processes.parse().map_err(|_| {
+                ConfigError::InvalidCollector("TOP_PROCESSES", top_processes.clone())
+            })?,
+            gpu: match gpu.as_str() {
+                "" | "true" | "1" => true,
+                "false" | "0" => false,
+                _ => return Err(ConfigError::InvalidCollector("COLLECT_GPU", gpu)),
+            },
+            mount_points: match source.var("DISK_MOUNT_POINTS") {
+                Some(value) => parse_list(&value),
+                None => CollectorConfig::default().mount_points,
+            },
+            exclude_interfaces: parse_list(&source.var("NETWORK_EXCLUDE").unwrap_or_default()),
+            exclude_processes: parse_list(&source.var("PROCESS_EXCLUDE").unwrap_or_default()),
+        };
+
+        let history_limit = source
+            .var("HISTORY_LIMIT")
+            .unwrap_or_else(|| "288".to_string());
         let history_limit: usize = history_limit
             .parse()
             .map_err(|_| ConfigError::InvalidHistory(history_limit.clone()))?;
 
+        let archive_window = source
+            .var("ARCHIVE_WINDOW_SECS")
+            .unwrap_or_else(|| "3600".to_string());
+        let archive_window_secs: u64 = archive_window
+            .parse()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| ConfigError::InvalidArchiveWindow(archive_window.clone()))?;
+
+        // A budget of 0 disables pruning of archived snapshots.
+        let storage_budget = source
+            .var("STORAGE_MAX_MB")
+            .unwrap_or_else(|| "1024".to_string());
+        let storage_budget_mb: u64 = storage_budget
+            .parse()
+            .map_err(|_| ConfigError::InvalidStorageBudget(storage_budget.clone()))?;
+
+        let ui_password = match source.var("UI_PASSWORD") {
+            Some(password) => parse_secret("UI_PASSWORD", &password)?,
+            None => None,
+        };
+        let session_ttl = source
+            .var("SESSION_TTL_SECS")
+            .unwrap_or_else(|| "28800".to_string());
+        let session_ttl_secs: u64 = session_ttl
+            .parse()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| ConfigError::InvalidSessionTtl(session_ttl.clone()))?;
+
+        let rate_limits = RateLimits {
+            per_client_per_minute: parse_limit(source, "RATE_LIMIT_PER_MINUTE", "600")?,
+            history_per_minute: parse_limit(source, "HISTORY_RATE_LIMIT_PER_MINUTE", "120")?,
+            auth_failure_limit: parse_limit(source, "AUTH_FAILURE_LIMIT", "10")?,
+            lockout: Duration::from_secs(parse_limit(source, "AUTH_LOCKOUT_SECS", "900")?.into()),
+            exempt: parse_cidrs(source, "RATE_LIMIT_EXEMPT")?,
+        };
+
+        let access = AccessRules {
+            allow: parse_cidrs(source, "ACCESS_ALLOW")?,
+            deny: parse_cidrs(source, "ACCESS_DENY")?,
+            trusted_proxies: parse_cidrs(source, "TRUSTED_PROXIES")?,
+        };
+
+        // An empty AUDIT_LOG disables the audit log.
+        let audit_path = source
+            .var("AUDIT_LOG")
+            .unwrap_or_else(|| "data/audit/audit.jsonl".into());
+        let audit = if audit_path.is_empty() {
+            None
+        } else {
+            let max_mb = source
+                .var("AUDIT_MAX_MB")
+                .unwrap_or_else(|| "50".to_string());
+            let max_mb: u64 = max_mb
+                .parse()
+                .ok()
+                .filter(|mb| *mb > 0)
+                .ok_or_else(|| ConfigError::InvalidAudit("AUDIT_MAX_MB", max_mb.clone()))?;
+            let max_files = source
+                .var("AUDIT_MAX_FILES")
+                .unwrap_or_else(|| "5".to_string());
+            let max_files: usize = max_files
+                .parse()
+                .map_err(|_| ConfigError::InvalidAudit("AUDIT_MAX_FILES", max_files.clone()))?;
+            Some(AuditConfig {
+                path: PathBuf::from(audit_path),
+                max_bytes: max_mb * 1024 * 1024,
+                max_files,
+            })
+        };
+
+        let tls = match (source.path("TLS_CERT"), source.path("TLS_KEY")) {
+            (Some(cert), Some(key)) => {
+                let required = source.var("TLS_CLIENT_CERT_REQUIRED").unwrap_or_default();
+                let client_cert_required = match required.as_str() {
+                    "" | "false" | "0" => false,
+                    "true" | "1" => true,
+                    _ => {
+                        return Err(ConfigError::InvalidTls(
+                            "TLS_CLIENT_CERT_REQUIRED",
+                            required,
+                        ))
+                    }
+                };
+                let client_ca = source.path("TLS_CLIENT_CA");
+                if client_cert_required && client_ca.is_none() {
+                    return Err(ConfigError::InvalidTls(
+                        "TLS_CLIENT_CERT_REQUIRED",
+                        "requires TLS_CLIENT_CA".to_string(),
+                    ));
+                }
+                let scopes = source
+                    .var("TLS_CLIENT_SCOPES")
+                    .unwrap_or_else(|| "read-metrics".to_string());
+                let client_scopes = scopes
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|scope| !scope.is_empty())
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| ConfigError::InvalidTls("TLS_CLIENT_SCOPES", err))?;
+                Some(TlsConfig {
+                    cert,
+                    key,
+                    client_ca,
+                    client_cert_required,
+                    client_scopes,
+                })
+            }
+            (None, None) => None,
+            _ => return Err(ConfigError::IncompleteTls),
+        };
+
+        let signing_key = source
+            .var("TOKEN_SIGNING_KEY")
+            .filter(|key| !key.is_empty())
+            .map(String::into_bytes);
+        if signing_key.as_ref().is_some_and(|key| key.len() < 32) {
+            return Err(ConfigError::InvalidToken(
+                "TOKEN_SIGNING_KEY",
+                "must be at least 32 bytes long".to_string(),
+            ));
+        }
+        let max_ttl = parse_token_ttl(source, "TOKEN_MAX_TTL_SECS", "3600")?;
+        let default_ttl = parse_token_ttl(source, "TOKEN_TTL_SECS", "300")?;
+        if default_ttl > max_ttl {
+            return Err(ConfigError::InvalidToken(
+                "TOKEN_TTL_SECS",
+                "exceeds TOKEN_MAX_TTL_SECS".to_string(),
+            ));
+        }
+        let in_query = source.var("API_KEYS_IN_QUERY").unwrap_or_default();
+        let keys_in_query = match in_query.as_str() {
+            "" | "true" | "1" => true,
+            "false" | "0" => false,
+            _ => return Err(ConfigError::InvalidToken("API_KEYS_IN_QUERY", in_query)),
+        };
+        let tokens = TokenConfig {
+            signing_key,
+            default_ttl,
+            max_ttl,
+            keys_in_query,
+        };
+
+        // Readiness fails once the latest snapshot is older than this many intervals.
+        let stale = source
+            .var("READY_STALE_INTERVALS")
+            .unwrap_or_else(|| "3".to_string());
+        let ready_stale_intervals: u32 = stale
+            .parse()
+            .ok()
+            .filter(|intervals| *intervals > 0)
+            .ok_or_else(|| ConfigError::InvalidReadiness(stale.clone()))?;
+
+        // Time allowed for draining requests and the last write after SIGTERM.
+        let shutdown_timeout = source
+            .var("SHUTDOWN_TIMEOUT_SECS")
+            .unwrap_or_else(|| "10".to_string());
+        let shutdown_timeout_secs: u64 = shutdown_timeout
+            .parse()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| ConfigError::InvalidShutdownTimeout(shutdown_timeout.clone()))?;
+
         Ok(Self {
             api_key,
+            api_keys_file,
+            api_keys: source.keys.clone(),
             bind_address,
             snapshot_dir,
             history_limit,
             collection_interval: Duration::from_secs_f64(collection_interval_secs.max(1.0)),
+            collector,
+            archive_window: Duration::from_secs(archive_window_secs),
+            storage_budget_bytes: (storage_budget_mb > 0).then(|| storage_budget_mb * 1024 * 1024),
+            ui_password,
+            session_ttl: Duration::from_secs(session_ttl_secs),
+            rate_limits,
+            access,
+            audit,
+            tls,
+            tokens,
+            ready_stale_intervals,
+            shutdown_timeout: Duration::from_secs(shutdown_timeout_secs),
+            config_file: None,
         })
     }
 
-    pub fn api_key(&self) -> &str {
-        &self.api_key
+    pub fn api_key(&self) -> Option<&Secret> {
+        self.api_key.as_ref()
+    }
+
+    pub fn api_keys_file(&self) -> Option<&Path> {
+        self.api_keys_file.as_deref()
+    }
+
+    /// Named keys from `[[auth.keys]]` in the config file.
+    pub fn api_keys(&self) -> &[ApiKey] {
+        &self.api_keys
     }
 
     pub fn bind_address(&self) -> &str {
@@ -72,4 +568,430 @@ impl Config {
     pub fn collection_interval(&self) -> Duration {
         self.collection_interval
     }
+
+    pub fn collector(&self) -> &CollectorConfig {
+        &self.collector
+    }
+
+    pub fn archive_window(&self) -> Duration {
+        self.archive_window
+    }
+
+    pub fn storage_budget_bytes(&self) -> Option<u64> {
+        self.storage_budget_bytes
+    }
+
+    pub fn ui_password(&self) -> Option<&Secret> {
+        self.ui_password.as_ref()
+    }
+
+    pub fn session_ttl(&self) -> Duration {
+        self.session_ttl
+    }
+
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limits
+    }
+
+    pub fn access(&self) -> &AccessRules {
+        &self.access
+    }
+
+    pub fn audit(&self) -> Option<&AuditConfig> {
+        self.audit.as_ref()
+    }
+
+    pub fn tls(&self) -> Option<&TlsConfig> {
+        self.tls.as_ref()
+    }
+
+    pub fn tokens(&self) -> &TokenConfig {
+        &self.tokens
+    }
+
+    pub fn config_file(&self) -> Option<&Path> {
+        self.config_file.as_deref()
+    }
+
+    pub fn ready_stale_intervals(&self) -> u32 {
+        self.ready_stale_intervals
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    /// Copies the settings that are bound at startup (listen address, TLS,
+    /// audit log, the locked snapshot directory) from `running` and returns
+    /// the names of those that differ.
+    pub fn keep_startup_settings(&mut self, running: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.bind_address != running.bind_address {
+            changed.push("API_BIND_ADDRESS");
+        }
+        if self.snapshot_dir != running.snapshot_dir {
+            changed.push("SNAPSHOT_DIR");
+        }
+        if self.tls != running.tls {
+            changed.push("TLS");
+        }
+        if self.audit != running.audit {
+            changed.push("AUDIT_LOG");
+        }
+        self.bind_address = running.bind_address.clone();
+        self.snapshot_dir = running.snapshot_dir.clone();
+        self.tls = running.tls.clone();
+        self.audit = running.audit.clone();
+        changed
+    }
+
+    pub fn summary(&self) -> ConfigSummary {
+        let cidrs = |nets: &[IpNet]| nets.iter().map(ToString::to_string).collect();
+        ConfigSummary {
+            config_file: self
+                .config_file
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            bind_address: self.bind_address.clone(),
+            snapshot_dir: self.snapshot_dir.display().to_string(),
+            history_limit: self.history_limit,
+            collection_interval_secs: self.collection_interval.as_secs_f64(),
+            top_processes: self.collector.top_processes,
+            collect_gpu: self.collector.gpu,
+            disk_mount_points: self.collector.mount_points.clone(),
+            network_exclude: self.collector.exclude_interfaces.clone(),
+            process_exclude: self.collector.exclude_processes.clone(),
+            archive_window_secs: self.archive_window.as_secs(),
+            storage_budget_mb: self.storage_budget_bytes.map(|bytes| bytes / 1024 / 1024),
+            api_key: match &self.api_key {
+                Some(key) if key.is_hashed() => "hashed",
+                Some(_) => "plaintext",
+                None => "none",
+            },
+            api_keys_file: self
+                .api_keys_file
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            inline_api_keys: self.api_keys.len(),
+            ui_password: self.ui_password.is_some(),
+            session_ttl_secs: self.session_ttl.as_secs(),
+            rate_limit_per_minute: self.rate_limits.per_client_per_minute,
+            history_rate_limit_per_minute: self.rate_limits.history_per_minute,
+            auth_failure_limit: self.rate_limits.auth_failure_limit,
+            access_allow: cidrs(&self.access.allow),
+            access_deny: cidrs(&self.access.deny),
+            trusted_proxies: cidrs(&self.access.trusted_proxies),
+            audit_log: self
+                .audit
+                .as_ref()
+                .map(|audit| audit.path.display().to_string()),
+            tls: self.tls.is_some(),
+            client_certificates: self.tls.as_ref().is_some_and(|tls| tls.client_ca.is_some()),
+            token_signing_key: self.tokens.signing_key.is_some(),
+            api_keys_in_query: self.tokens.keys_in_query,
+            ready_stale_intervals: self.ready_stale_intervals,
+            shutdown_timeout_secs: self.shutdown_timeout.as_secs(),
+        }
+    }
+}
+
+fn parse_secret(name: &'static str, value: &str) -> Result<Option<Secret>, ConfigError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value
+        .parse()
+        .map(Some)
+        .map_err(|err| ConfigError::InvalidSecret(name, err))
+}
+
+fn parse_limit(source: &Source, name: &'static str, default: &str) -> Result<u32, ConfigError> {
+    let value = source.var(name).unwrap_or_else(|| default.to_string());
+    value
+        .parse()
+        .map_err(|_| ConfigError::InvalidRateLimit(name, value.clone()))
+}
+
+fn parse_token_ttl(
+    source: &Source,
+    name: &'static str,
+    default: &str,
+) -> Result<Duration, ConfigError> {
+    let value = source.var(name).unwrap_or_else(|| default.to_string());
+    value
+        .parse()
+        .ok()
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+        .ok_or(ConfigError::InvalidToken(name, value))
+}
+
+/// Splits a comma-separated list, dropping empty entries.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses a comma-separated list of CIDRs; bare addresses match a single host.
+fn parse_cidrs(source: &Source, name: &'static str) -> Result<Vec<IpNet>, ConfigError> {
+    let value = source.var(name).unwrap_or_default();
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<std::net::IpAddr>().map(IpNet::from))
+                .map_err(|_| ConfigError::InvalidCidr(name, entry.to_string()))
+        })
+        .collect()
+}
+
+/// Keys of the config file (`[section]`, key) and the environment variables
+/// that override them.
+const FILE_KEYS: &[(&str, &str, &str)] = &[
+    ("server", "bind_address", "API_BIND_ADDRESS"),
+    ("server", "shutdown_timeout_secs", "SHUTDOWN_TIMEOUT_SECS"),
+    ("collector", "interval_secs", "COLLECTION_INTERVAL_SECS"),
+    ("collector", "history_limit", "HISTORY_LIMIT"),
+    ("collector", "top_processes", "TOP_PROCESSES"),
+    ("collector", "gpu", "COLLECT_GPU"),
+    ("filters", "mount_points", "DISK_MOUNT_POINTS"),
+    ("filters", "exclude_interfaces", "NETWORK_EXCLUDE"),
+    ("filters", "exclude_processes", "PROCESS_EXCLUDE"),
+    ("storage", "snapshot_dir", "SNAPSHOT_DIR"),
+    ("storage", "archive_window_secs", "ARCHIVE_WINDOW_SECS"),
+    ("storage", "max_mb", "STORAGE_MAX_MB"),
+    ("auth", "api_key", "SYSTEM_API_KEY"),
+    ("auth", "api_keys_file", "API_KEYS_FILE"),
+    ("auth", "ui_password", "UI_PASSWORD"),
+    ("auth", "session_ttl_secs", "SESSION_TTL_SECS"),
+    ("auth", "keys_in_query", "API_KEYS_IN_QUERY"),
+    ("tokens", "signing_key", "TOKEN_SIGNING_KEY"),
+    ("tokens", "ttl_secs", "TOKEN_TTL_SECS"),
+    ("tokens", "max_ttl_secs", "TOKEN_MAX_TTL_SECS"),
+    ("access", "allow", "ACCESS_ALLOW"),
+    ("access", "deny", "ACCESS_DENY"),
+    ("access", "trusted_proxies", "TRUSTED_PROXIES"),
+    ("rate_limit", "per_minute", "RATE_LIMIT_PER_MINUTE"),
+    (
+        "rate_limit",
+        "history_per_minute",
+        "HISTORY_RATE_LIMIT_PER_MINUTE",
+    ),
+    ("rate_limit", "auth_failure_limit", "AUTH_FAILURE_LIMIT"),
+    ("rate_limit", "lockout_secs", "AUTH_LOCKOUT_SECS"),
+    ("rate_limit", "exempt", "RATE_LIMIT_EXEMPT"),
+    ("audit", "path", "AUDIT_LOG"),
+    ("audit", "max_mb", "AUDIT_MAX_MB"),
+    ("audit", "max_files", "AUDIT_MAX_FILES"),
+    ("tls", "cert", "TLS_CERT"),
+    ("tls", "key", "TLS_KEY"),
+    ("tls", "client_ca", "TLS_CLIENT_CA"),
+    ("tls", "client_cert_required", "TLS_CLIENT_CERT_REQUIRED"),
+    ("tls", "client_scopes", "TLS_CLIENT_SCOPES"),
+    ("health", "ready_stale_intervals", "READY_STALE_INTERVALS"),
+];
+
+/// Settings set in the process environment, which take precedence over the
+/// config file. `.env` is only read at startup, so its entries count too.
+pub fn environment_overrides() -> Vec<&'static str> {
+    FILE_KEYS
+        .iter()
+        .map(|(_, _, variable)| *variable)
+        .filter(|variable| env::var_os(variable).is_some())
+        .collect()
+}
+
+/// Where settings come from: the environment first, then the config file.
+#[derive(Debug, Default)]
+struct Source {
+    path: Option<PathBuf>,
+    /// Values from the file by the environment variable they stand for.
+    file: HashMap<&'static str, FileValue>,
+    /// `[[auth.keys]]` entries, which have no environment variable.
+    keys: Vec<ApiKey>,
+    /// Only consult the file (tests).
+    ignore_environment: bool,
+}
+
+/// The `[[auth.keys]]` array, read on its own to keep the line of each entry.
+#[derive(Deserialize)]
+struct KeysFile {
+    #[serde(default)]
+    auth: KeysSection,
+}
+
+#[derive(Default, Deserialize)]
+struct KeysSection {
+    #[serde(default)]
+    keys: Vec<Spanned<toml::Table>>,
+}
+
+#[derive(Debug)]
+struct FileValue {
+    key: String,
+    line: usize,
+    value: String,
+}
+
+impl Source {
+    fn file(path: &Path) -> Result<Self, ConfigError> {
+        let display = path.display().to_string();
+        let content =
+            fs::read_to_string(path).map_err(|err| ConfigError::ReadFile(display.clone(), err))?;
+        // Spanning the section name rather than its table also works for tables
+        // only opened implicitly, as `[[auth.keys]]` does for `auth`.
+        let sections: BTreeMap<Spanned<String>, BTreeMap<String, Spanned<toml::Value>>> =
+            toml::from_str(&content).map_err(|err| ConfigError::ParseFile(display.clone(), err))?;
+        let line_of = |offset: usize| content[..offset].matches('\n').count() + 1;
+
+        let mut file = HashMap::new();
+        for (section, entries) in sections {
+            let section_line = line_of(section.span().start);
+            let section = section.into_inner();
+            let mut known: Vec<&str> = FILE_KEYS
+                .iter()
+                .filter(|(s, _, _)| *s == section)
+                .map(|(_, name, _)| *name)
+                .collect();
+            if section == "auth" {
+                known.push("keys");
+            }
+            if known.is_empty() {
+                return Err(ConfigError::FileEntry {
+                    path: display,
+                    line: section_line,
+                    key: section,
+                    message: "unknown section".to_string(),
+                });
+            }
+
+            for (name, value) in entries {
+                if section == "auth" && name == "keys" {
+                    continue;
+                }
+                let key = format!("{section}.{name}");
+                let line = line_of(value.span().start);
+                let entry_error = |message: String| ConfigError::FileEntry {
+                    path: display.clone(),
+                    line,
+                    key: key.clone(),
+                    message,
+                };
+
+                let Some(&(_, _, variable)) = FILE_KEYS
+                    .iter()
+                    .find(|(s, n, _)| *s == section && *n == name)
+                else {
+                    return Err(entry_error(format!(
+                        "unknown key, expected one of: {}",
+                        known.join(", ")
+                    )));
+                };
+                let value = scalar(value.into_inner()).ok_or_else(|| {
+                    entry_error("expected a string, number, boolean or a list of those".into())
+                })?;
+                file.insert(variable, FileValue { key, line, value });
+            }
+        }
+
+        let inline: KeysFile =
+            toml::from_str(&content).map_err(|err| ConfigError::ParseFile(display.clone(), err))?;
+        let mut keys: Vec<ApiKey> = Vec::new();
+        for (index, entry) in inline.auth.keys.into_iter().enumerate() {
+            let line = line_of(entry.span().start);
+            let entry_error = |message: String| ConfigError::FileEntry {
+                path: display.clone(),
+                line,
+                key: format!("auth.keys[{index}]"),
+                message,
+            };
+            let key =
+                ApiKey::deserialize(toml::Value::Table(with_string_dates(entry.into_inner())))
+                    .map_err(|err| entry_error(err.message().to_string()))?;
+            if keys.iter().any(|other| other.name == key.name) {
+                return Err(entry_error(format!("duplicate key name `{}`", key.name)));
+            }
+            keys.push(key);
+        }
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            file,
+            keys,
+            ignore_environment: false,
+        })
+    }
+
+    /// The environment variable if set, otherwise the file value.
+    fn var(&self, name: &str) -> Option<String> {
+        self.env(name)
+            .or_else(|| self.file.get(name).map(|entry| entry.value.clone()))
+    }
+
+    fn env(&self, name: &str) -> Option<String> {
+        if self.ignore_environment {
+            return None;
+        }
+        env::var(name).ok()
+    }
+
+    /// Like [`Source::var`], with an empty value meaning unset.
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        self.var(name)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    }
+
+    /// Points an error about a value that came from the file at its key and line.
+    fn locate(&self, err: ConfigError) -> ConfigError {
+        let entry = err
+            .variable()
+            .filter(|name| self.env(name).is_none())
+            .and_then(|name| self.file.get(name));
+        match (entry, &self.path) {
+            (Some(entry), Some(path)) => ConfigError::FileEntry {
+                path: path.display().to_string(),
+                line: entry.line,
+                key: entry.key.clone(),
+                message: err.to_string(),
+            },
+            _ => err,
+        }
+    }
+}
+
+/// Turns TOML dates into RFC 3339 strings, so `expires_at` may be written
+/// either way.
+fn with_string_dates(table: toml::Table) -> toml::Table {
+    table
+        .into_iter()
+        .map(|(name, value)| match value {
+            toml::Value::Datetime(date) => (name, toml::Value::String(date.to_string())),
+            value => (name, value),
+        })
+        .collect()
+}
+
+/// Converts a file value to the string form of the environment variable;
+/// lists become comma-separated.
+fn scalar(value: toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Array(items) => items
+            .into_iter()
+            .map(scalar)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        _ => None,
+    }
 }
@@ -0,0 +1,323 @@
+//! Endpoints for Grafana's JSON datasource (SimpleJSON protocol). Point the
+//! datasource URL at `http://<agent>/grafana`.
+
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    api::{self, ApiError, ApiJson, Caller},
+    catalog,
+    collector::SystemSnapshot,
+    keys::Scope,
+    series::{self, Aggregation, MetricPath},
+    state::SharedState,
+};
+
+/// Array fields of [`SystemSnapshot`] that can be queried as tables.
+const TABLE_TARGETS: [&str; 4] = ["top_processes", "disks", "network", "gpus"];
+
+pub fn router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(health).post(health))
+        .route("/search", post(search))
+        .route("/query", post(query))
+        .route("/annotations", post(annotations))
+        .route("/tag-keys", post(tag_keys))
+        .route("/tag-values", post(tag_values))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TimeRange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl TimeRange {
+    fn millis(&self) -> Result<(Option<i64>, Option<i64>), ApiError> {
+        Ok((parse_time(&self.from)?, parse_time(&self.to)?))
+    }
+}
+
+fn parse_time(value: &Option<String>) -> Result<Option<i64>, ApiError> {
+    let Some(raw) = value else {
+        return Ok(None);
+    };
+    DateTime::parse_from_rfc3339(raw)
+        .map(|dt| Some(dt.timestamp_millis()))
+        .map_err(|_| ApiError::BadRequest(format!("invalid time: {raw}")))
+}
+
+pub async fn health(
+    State(_state): State<SharedState>,
+    caller: Caller,
+) -> Result<&'static str, ApiError> {
+    caller.authorise(Scope::ReadMetrics)?;
+    Ok("OK")
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchRequest {
+    pub target: String,
+}
+
+async fn search(
+    State(state): State<SharedState>,
+    caller: Caller,
+    body: Option<Json<SearchRequest>>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    caller.authorise(Scope::ReadMetrics)?;
+
+    let filter = body.map(|Json(req)| req.target).unwrap_or_default();
+    let snapshot = state.latest_snapshot().await;
+
+    let mut targets: Vec<String> = TABLE_TARGETS.iter().map(|t| t.to_string()).collect();
+    targets.extend(
+        catalog::catalog(&snapshot)
+            .into_iter()
+            .map(|entry| entry.path),
+    );
+    targets.retain(|target| target.contains(filter.trim()));
+
+    Ok(Json(targets))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryRequest {
+    #[serde(default)]
+    pub range: TimeRange,
+    pub interval_ms: Option<u64>,
+    #[serde(default)]
+    pub targets: Vec<QueryTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryTarget {
+    #[serde(default)]
+    pub target: String,
+    pub ref_id: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub hide: bool,
+    pub data: Option<Value>,
+    pub payload: Option<Value>,
+}
+
+impl QueryTarget {
+    fn aggregation(&self) -> Result<Aggregation, ApiError> {
+        let agg = self
+            .data
+            .as_ref()
+            .or(self.payload.as_ref())
+            .and_then(|options| options.get("agg"))
+            .and_then(Value::as_str)
+            .unwrap_or("avg");
+        Ok(agg.parse()?)
+    }
+}
+
+async fn query(
+    State(state): State<SharedState>,
+    caller: Caller,
+    ApiJson(request): ApiJson<QueryRequest>,
+) -> Result<Json<Vec<Value>>, ApiError> {
+    let principal = caller.authorise(Scope::ReadMetrics)?;
+
+    let (from, to) = request.range.millis()?;
+    let mut snapshots = api::snapshots_in_range(&state, from, to).await?;
+    api::redact_processes(&principal, &mut snapshots);
+
+    // Only aggregate when Grafana asks for coarser points than we collect.
+    let collection_ms = state.config().collection_interval().as_millis() as u64;
+    let step = request
+        .interval_ms
+        .filter(|ms| *ms > collection_ms)
+        .map(Duration::from_millis);
+
+    let mut results = Vec::new();
+    for target in request
+        .targets
+        .iter()
+        .filter(|t| !t.hide && !t.target.is_empty())
+    {
+        let name = target.target.trim();
+        if target.kind.as_deref() == Some("table") {
+            if name == "top_processes" && !principal.allows(Scope::ReadProcesses) {
+                return Err(ApiError::Forbidden {
+                    key: principal.name,
+                    scope: Scope::ReadProcesses,
+                });
+            }
+            results.push(table_response(name, &snapshots)?);
+            continue;
+        }
+
+        let path: MetricPath = name.parse()?;
+        let datapoints: Vec<(f64, i64)> =
+            series::build_series(&snapshots, &path, step, target.aggregation()?)
+                .into_iter()
+                .map(|(ts, value)| (value, ts))
+                .collect();
+
+        results.push(json!({
+            "target": name,
+            "refId": target.ref_id,
+            "datapoints": datapoints,
+        }));
+    }
+
+    Ok(Json(results))
+}
+
+/// Array targets (processes, disks, ...) become one row per element of the
+/// latest snapshot in range; any other metric path becomes a time/value table.
+fn table_response(target: &str, snapshots: &[SystemSnapshot]) -> Result<Value, ApiError> {
+    if TABLE_TARGETS.contains(&target) {
+        let rows_source = snapshots
+            .last()
+            .and_then(|snapshot| serde_json::to_value(snapshot).ok())
+            .and_then(|value| value.get(target).cloned())
+            .and_then(|value| value.as_array().cloned())
+            .unwrap_or_default();
+
+        let objects: Vec<Map<String, Value>> = rows_source
+            .into_iter()
+            .filter_map(|row| row.as_object().cloned())
+            .collect();
+
+        let columns: Vec<String> = objects
+            .first()
+            .map(|row| row.keys().cloned().collect())
+            .unwrap_or_default();
+
+        let column_defs: Vec<Value> = columns
+            .iter()
+            .map(|name| {
+                let kind = match objects.first().and_then(|row| row.get(name)) {
+                    Some(Value::Number(_)) => "number",
+                    _ => "string",
+                };
+                json!({ "text": name, "type": kind })
+            })
+            .collect();
+
+        let rows: Vec<Vec<Value>> = objects
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|column| row.get(column).cloned().unwrap_or(Value::Null))
+                    .collect()
+            })
+            .collect();
+
+        return Ok(json!({ "type": "table", "columns": column_defs, "rows": rows }));
+    }
+
+    let path: MetricPath = target.parse()?;
+    let rows: Vec<(i64, f64)> = series::build_series(snapshots, &path, None, Aggregation::Last);
+
+    Ok(json!({
+        "type": "table",
+        "columns": [
+            { "text": "Time", "type": "time" },
+            { "text": target, "type": "number" },
+        ],
+        "rows": rows,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotationRequest {
+    #[serde(default)]
+    pub range: TimeRange,
+    #[serde(default)]
+    pub annotation: Value,
+}
+
+/// Reports host restarts, detected as a drop in `uptime_seconds` between two
+/// consecutive snapshots.
+async fn annotations(
+    State(state): State<SharedState>,
+    caller: Caller,
+    ApiJson(request): ApiJson<AnnotationRequest>,
+) -> Result<Json<Vec<Value>>, ApiError> {
+    caller.authorise(Scope::ReadMetrics)?;
+
+    let (from, to) = request.range.millis()?;
+    let snapshots = api::snapshots_in_range(&state, from, to).await?;
+
+    let annotations = snapshots
+        .windows(2)
+        .filter(|pair| pair[1].uptime_seconds < pair[0].uptime_seconds)
+        .map(|pair| {
+            let restarted = &pair[1];
+            let host = restarted.hostname.as_deref().unwrap_or("host");
+            json!({
+                "annotation": request.annotation,
+                "time": restarted.timestamp - (restarted.uptime_seconds as i64) * 1000,
+                "title": "Host restarted",
+                "text": format!("{host} restarted"),
+                "tags": ["restart"],
+            })
+        })
+        .collect();
+
+    Ok(Json(annotations))
+}
+
+async fn tag_keys(
+    State(_state): State<SharedState>,
+    caller: Caller,
+) -> Result<Json<Value>, ApiError> {
+    caller.authorise(Scope::ReadMetrics)?;
+
+    Ok(Json(json!([
+        { "type": "string", "text": "hostname" },
+        { "type": "string", "text": "mount_point" },
+        { "type": "string", "text": "interface" },
+        { "type": "string", "text": "gpu" },
+    ])))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagValuesRequest {
+    pub key: String,
+}
+
+async fn tag_values(
+    State(state): State<SharedState>,
+    caller: Caller,
+    ApiJson(request): ApiJson<TagValuesRequest>,
+) -> Result<Json<Vec<Value>>, ApiError> {
+    caller.authorise(Scope::ReadMetrics)?;
+
+    let snapshot = state.latest_snapshot().await;
+    let values: Vec<String> = match request.key.as_str() {
+        "hostname" => snapshot.hostname.into_iter().collect(),
+        "mount_point" => snapshot.disks.into_iter().map(|d| d.mount_point).collect(),
+        "interface" => snapshot.network.into_iter().map(|n| n.name).collect(),
+        "gpu" => snapshot.gpus.iter().map(|g| g.index.to_string()).collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(Json(
+        values
+            .into_iter()
+            .map(|text| json!({ "text": text }))
+            .collect(),
+    ))
+}
@@ -0,0 +1,476 @@
+//! Liveness and readiness probes, the agent's self-metrics and the
+//! `/api/agent/status` report.
+
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use crate::{
+    api::{ApiError, Caller},
+    collector::{AgentMetrics, CollectionReport, SectionMetrics},
+    config::ConfigSummary,
+    keys::Scope,
+    state::SharedState,
+    storage::{self, StorageError},
+};
+
+// Alert events buffered per WebSocket subscriber before it lags.
+const ALERT_CHANNEL_CAPACITY: usize = 64;
+
+/// Collection and storage outcomes recorded by the scheduler.
+#[derive(Debug)]
+pub struct AgentHealth {
+    started: Instant,
+    started_at: DateTime<Utc>,
+    inner: Mutex<HealthState>,
+    alerts: broadcast::Sender<AlertEvent>,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    collector_failures: u64,
+    overruns: u64,
+    last_collection_at: Option<DateTime<Utc>>,
+    collection: Timing,
+    sources: BTreeMap<String, SourceStatus>,
+    storage: StorageStatus,
+    process: Option<ProcessUsage>,
+    reload: ReloadStatus,
+    /// Alerts currently firing, by source.
+    firing: BTreeMap<String, AlertEvent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// A failure of the agent itself, or its recovery. Sources are collection
+/// sections (`cpu`, `nvml`, ...), `collector`, `storage` and `reload`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AlertEvent {
+    pub source: String,
+    pub state: AlertState,
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Outcomes of configuration reloads (SIGHUP or `/api/config/reload`).
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ReloadStatus {
+    pub reloads: u64,
+    pub failures: u64,
+    pub last_at: Option<DateTime<Utc>>,
+    /// Why the most recent reload was rejected; cleared by a successful one.
+    pub last_error: Option<String>,
+}
+
+/// Running statistics of a duration.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct Timing {
+    pub count: u64,
+    pub last_ms: f64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Timing {
+    fn add(&mut self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        self.count += 1;
+        self.last_ms = ms;
+        self.mean_ms += (ms - self.mean_ms) / self.count as f64;
+        self.max_ms = self.max_ms.max(ms);
+    }
+}
+
+/// One section of the collection (`cpu`, `processes`, `nvml`, ...).
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct SourceStatus {
+    pub duration: Timing,
+    pub errors: u64,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct StorageStatus {
+    /// Size of the snapshot directory; absent when it cannot be read.
+    pub size_bytes: Option<u64>,
+    /// Latency of snapshot writes, failed ones included.
+    pub writes: Timing,
+    pub failed_writes: u64,
+    /// Failed writes since the last successful one.
+    pub consecutive_failures: u64,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+/// CPU usage and resident memory of the agent process.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProcessUsage {
+    pub cpu_pct: f64,
+    pub rss_mb: u64,
+}
+
+impl Default for AgentHealth {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: Utc::now(),
+            inner: Mutex::new(HealthState::default()),
+            alerts: broadcast::channel(ALERT_CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl HealthState {
+    /// Fires an alert for `source` when it starts failing and resolves it once
+    /// it succeeds again; repeated failures do not fire again.
+    fn transition(
+        &mut self,
+        source: &str,
+        error: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Option<AlertEvent> {
+        match error {
+            Some(message) if !self.firing.contains_key(source) => {
+                let event = AlertEvent {
+                    source: source.to_string(),
+                    state: AlertState::Firing,
+                    message,
+                    at: now,
+                };
+                self.firing.insert(source.to_string(), event.clone());
+                Some(event)
+            }
+            None => self.firing.remove(source).map(|fired| AlertEvent {
+                state: AlertState::Resolved,
+                message: format!("{source} recovered"),
+                at: now,
+                ..fired
+            }),
+            Some(_) => None,
+        }
+    }
+}
+
+impl AgentHealth {
+    /// Records a finished collection and returns the metrics to attach to its
+    /// snapshot.
+    pub fn record_collection(
+        &self,
+        duration: Duration,
+        interval: Duration,
+        report: &CollectionReport,
+    ) -> AgentMetrics {
+        let now = Utc::now();
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.collection.add(duration);
+        inner.last_collection_at = Some(now);
+        if duration > interval {
+            inner.overruns += 1;
+        }
+        for (section, elapsed) in &report.timings {
+            inner
+                .sources
+                .entry(section.to_string())
+                .or_default()
+                .duration
+                .add(*elapsed);
+        }
+        for error in &report.errors {
+            let source = inner.sources.entry(error.source.to_string()).or_default();
+            source.errors += 1;
+            source.last_error = Some(error.message.clone());
+            source.last_error_at = Some(now);
+        }
+        let mut events: Vec<_> = report
+            .timings
+            .iter()
+            .filter_map(|(section, _)| {
+                let error = report
+                    .errors
+                    .iter()
+                    .find(|error| error.source == *section)
+                    .map(|error| error.message.clone());
+                inner.transition(section, error, now)
+            })
+            .collect();
+        events.extend(inner.transition("collector", None, now));
+        inner.process = report
+            .agent_cpu_pct
+            .zip(report.agent_rss_mb)
+            .map(|(cpu_pct, rss_mb)| ProcessUsage { cpu_pct, rss_mb });
+
+        let metrics = AgentMetrics {
+            collection_ms: inner.collection.last_ms,
+            sections: report
+                .timings
+                .iter()
+                .map(|(section, elapsed)| SectionMetrics {
+                    name: section.to_string(),
+                    duration_ms: elapsed.as_secs_f64() * 1000.0,
+                    failures: inner.sources.get(*section).map_or(0, |s| s.errors),
+                })
+                .collect(),
+            storage_write_ms: (inner.storage.writes.count > 0)
+                .then_some(inner.storage.writes.last_ms),
+            overruns: inner.overruns,
+            cpu_pct: report.agent_cpu_pct,
+            rss_mb: report.agent_rss_mb,
+        };
+        drop(inner);
+        self.publish(events);
+        metrics
+    }
+
+    /// The collector task itself failed (panicked) and produced no snapshot.
+    pub fn record_collector_failure(&self) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.collector_failures += 1;
+        let event = inner.transition(
+            "collector",
+            Some("collection task failed".to_string()),
+            Utc::now(),
+        );
+        drop(inner);
+        self.publish(event);
+    }
+
+    pub fn record_storage_write(&self, duration: Duration, error: Option<&StorageError>) {
+        let now = Utc::now();
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let storage = &mut inner.storage;
+        storage.writes.add(duration);
+        match error {
+            Some(err) => {
+                storage.failed_writes += 1;
+                storage.consecutive_failures += 1;
+                storage.last_error = Some(err.to_string());
+                storage.last_error_at = Some(now);
+            }
+            None => storage.consecutive_failures = 0,
+        }
+        let event = inner.transition("storage", error.map(ToString::to_string), now);
+        drop(inner);
+        self.publish(event);
+    }
+
+    pub fn record_reload(&self, error: Option<String>) {
+        let now = Utc::now();
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let reload = &mut inner.reload;
+        reload.last_at = Some(now);
+        match error {
+            Some(_) => reload.failures += 1,
+            None => reload.reloads += 1,
+        }
+        reload.last_error = error.clone();
+        let event = inner.transition("reload", error, now);
+        drop(inner);
+        self.publish(event);
+    }
+
+    /// Alert events from now on.
+    pub fn subscribe_alerts(&self) -> broadcast::Receiver<AlertEvent> {
+        self.alerts.subscribe()
+    }
+
+    /// Alerts currently firing, ordered by source.
+    pub fn firing_alerts(&self) -> Vec<AlertEvent> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.firing.values().cloned().collect()
+    }
+
+    fn publish(&self, events: impl IntoIterator<Item = AlertEvent>) {
+        for event in events {
+            // Nobody may be subscribed; the event is still listed while firing.
+            let _ = self.alerts.send(event);
+        }
+    }
+
+    fn storage(&self) -> StorageStatus {
+        self.inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .storage
+            .clone()
+    }
+}
+
+/// Liveness probe: answers as long as the process serves requests.
+pub async fn healthz() -> Json<serde_json::Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness probe: fails when the latest snapshot is older than
+/// `READY_STALE_INTERVALS` collection intervals or the last snapshot write
+/// failed.
+pub async fn readyz(State(state): State<SharedState>) -> (StatusCode, Json<serde_json::Value>) {
+    let config = state.config();
+    let max_age_ms =
+        config.collection_interval().as_millis() as i64 * i64::from(config.ready_stale_intervals());
+    let age_ms = Utc::now().timestamp_millis() - state.latest_snapshot().await.timestamp;
+    let snapshot_ok = age_ms <= max_age_ms;
+
+    let storage = state.health().storage();
+    let storage_ok = storage.consecutive_failures == 0;
+
+    let ready = snapshot_ok && storage_ok;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": {
+            "snapshot": { "ok": snapshot_ok, "age_ms": age_ms, "max_age_ms": max_age_ms },
+            "storage": {
+                "ok": storage_ok,
+                "consecutive_failures": storage.consecutive_failures,
+                "last_error": if storage_ok { None } else { storage.last_error },
+            },
+        },
+    });
+    (status, Json(body))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AgentStatus {
+    pub version: &'static str,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: u64,
+    pub config: ConfigSummary,
+    #[serde(flatten)]
+    pub metrics: SelfMetrics,
+    pub reload: ReloadStatus,
+    pub audit: AuditStatus,
+}
+
+/// What the agent itself costs: collection and write timings, failures and
+/// its CPU and memory usage.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SelfMetrics {
+    pub collection: CollectionStatus,
+    /// Keyed by section name.
+    pub sources: BTreeMap<String, SourceStatus>,
+    pub storage: StorageStatus,
+    pub process: Option<ProcessUsage>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CollectionStatus {
+    pub duration: Timing,
+    pub collector_failures: u64,
+    /// Collections that took longer than the collection interval.
+    pub overruns: u64,
+    pub last_at: Option<DateTime<Utc>>,
+    /// Timestamp of the latest snapshot, Unix milliseconds.
+    pub latest_snapshot: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditStatus {
+    pub enabled: bool,
+    /// Records dropped because the audit writer could not keep up.
+    pub dropped_records: u64,
+}
+
+/// Timings of each collection section, failure counts, storage write latency
+/// and the agent's own CPU and memory usage.
+#[utoipa::path(
+    get,
+    path = "/api/v1/agent/metrics",
+    tag = "metrics",
+    responses(
+        (status = 200, body = SelfMetrics),
+        (status = 401, body = api::ErrorResponse),
+        (status = 403, body = api::ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn metrics(
+    State(state): State<SharedState>,
+    caller: Caller,
+) -> Result<Json<SelfMetrics>, ApiError> {
+    caller.authorise(Scope::ReadMetrics)?;
+    Ok(Json(self_metrics(&state).await))
+}
+
+/// Reports version, uptime, configuration and the health of collection and
+/// storage.
+#[utoipa::path(
+    get,
+    path = "/api/v1/agent/status",
+    tag = "admin",
+    responses(
+        (status = 200, body = AgentStatus),
+        (status = 401, body = api::ErrorResponse),
+        (status = 403, body = api::ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn status(
+    State(state): State<SharedState>,
+    caller: Caller,
+) -> Result<Json<AgentStatus>, ApiError> {
+    caller.authorise(Scope::Admin)?;
+
+    let health = state.health();
+    Ok(Json(AgentStatus {
+        version: env!("CARGO_PKG_VERSION"),
+        started_at: health.started_at,
+        uptime_secs: health.started.elapsed().as_secs(),
+        config: state.config().summary(),
+        metrics: self_metrics(&state).await,
+        reload: health
+            .inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .reload
+            .clone(),
+        audit: AuditStatus {
+            enabled: state.audit().is_enabled(),
+            dropped_records: state.audit().dropped(),
+        },
+    }))
+}
+
+async fn self_metrics(state: &SharedState) -> SelfMetrics {
+    let latest_snapshot = state.latest_snapshot().await.timestamp;
+    let size_bytes = storage::stored_bytes(&state.config()).await.ok();
+
+    let inner = state
+        .health()
+        .inner
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    SelfMetrics {
+        collection: CollectionStatus {
+            duration: inner.collection.clone(),
+            collector_failures: inner.collector_failures,
+            overruns: inner.overruns,
+            last_at: inner.last_collection_at,
+            latest_snapshot,
+        },
+        sources: inner.sources.clone(),
+        storage: StorageStatus {
+            size_bytes,
+            ..inner.storage.clone()
+        },
+        process: inner.process.clone(),
+    }
+}
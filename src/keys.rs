@@ -0,0 +1,448 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Permission attached to an API key. `Admin` implies every other scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    ReadMetrics,
+    ReadProcesses,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadMetrics => "read-metrics",
+            Scope::ReadProcesses => "read-processes",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+const HASH_PREFIX: &str = "sha256$";
+const SALT_LEN: usize = 16;
+
+/// A configured credential, either as `sha256$<salt>$<digest>` (hex encoded,
+/// digest = SHA-256 of salt followed by the key) or, for compatibility, in
+/// plaintext. Presented tokens are compared in constant time either way.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Secret {
+    Plain(String),
+    Sha256 { salt: Vec<u8>, digest: Vec<u8> },
+}
+
+#[derive(Debug, Error)]
+#[error("malformed key hash, expected sha256$<salt hex>$<digest hex>")]
+pub struct SecretError;
+
+impl Secret {
+    /// Hashes `key` with a fresh random salt and returns the config entry.
+    pub fn hash(key: &str) -> String {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let digest = salted_digest(&salt, key);
+        format!("{HASH_PREFIX}{}${}", to_hex(&salt), to_hex(&digest))
+    }
+
+    pub fn is_hashed(&self) -> bool {
+        matches!(self, Secret::Sha256 { .. })
+    }
+
+    pub fn verify(&self, provided: &str) -> bool {
+        match self {
+            Secret::Plain(expected) => {
+                crate::auth::subtle_equals(provided.as_bytes(), expected.as_bytes())
+            }
+            Secret::Sha256 { salt, digest } => {
+                crate::auth::subtle_equals(&salted_digest(salt, provided), digest)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Secret {
+    type Err = SecretError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let Some(encoded) = value.strip_prefix(HASH_PREFIX) else {
+            return Ok(Secret::Plain(value.to_string()));
+        };
+        let (salt, digest) = encoded.split_once('$').ok_or(SecretError)?;
+        let salt = from_hex(salt).filter(|salt| !salt.is_empty());
+        let digest = from_hex(digest).filter(|digest| digest.len() == Sha256::output_size());
+        match (salt, digest) {
+            (Some(salt), Some(digest)) => Ok(Secret::Sha256 { salt, digest }),
+            _ => Err(SecretError),
+        }
+    }
+}
+
+impl TryFrom<String> for Secret {
+    type Error = SecretError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+// Never print credentials, not even in debug output.
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Secret::Plain(_) => f.write_str("Secret::Plain(<redacted>)"),
+            Secret::Sha256 { .. } => f.write_str("Secret::Sha256(<redacted>)"),
+        }
+    }
+}
+
+/// Generates a new random API key.
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn salted_digest(salt: &[u8], key: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(key.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl std::str::FromStr for Scope {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "read-metrics" => Ok(Scope::ReadMetrics),
+            "read-processes" => Ok(Scope::ReadProcesses),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!("unknown scope `{other}`")),
+        }
+    }
+}
+
+/// The authenticated caller of a request.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl Principal {
+    pub fn new(name: impl Into<String>, scopes: Vec<Scope>) -> Self {
+        Self {
+            name: name.into(),
+            scopes,
+        }
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes
+            .iter()
+            .any(|granted| *granted == scope || *granted == Scope::Admin)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: Secret,
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires| expires <= now)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyFile {
+    keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Error)]
+pub enum KeyFileError {
+    #[error("could not read key file {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid key file {0}: {1}")]
+    Parse(PathBuf, serde_json::Error),
+    #[error("key file {0} contains duplicate key name `{1}`")]
+    DuplicateName(PathBuf, String),
+}
+
+#[derive(Debug, Default)]
+struct Loaded {
+    keys: Vec<ApiKey>,
+    modified: Option<SystemTime>,
+}
+
+/// Named API keys from the key file. The file is re-read whenever its
+/// modification time changes, so keys can be rotated without a restart.
+#[derive(Debug)]
+pub struct KeyStore {
+    path: Option<PathBuf>,
+    loaded: RwLock<Loaded>,
+}
+
+impl KeyStore {
+    pub fn open(path: Option<&Path>) -> Result<Self, KeyFileError> {
+        let store = Self {
+            path: path.map(Path::to_path_buf),
+            loaded: RwLock::new(Loaded::default()),
+        };
+        if let Some(path) = &store.path {
+            let loaded = load(path)?;
+            info!(
+                "🔑 {} API key(s) loaded from {}",
+                loaded.keys.len(),
+                path.display()
+            );
+            *store.loaded.write().unwrap_or_else(|e| e.into_inner()) = loaded;
+        }
+        Ok(store)
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Finds the unexpired key matching `secret`.
+    pub fn authenticate(&self, secret: &str) -> Option<Principal> {
+        self.reload_if_changed();
+
+        let loaded = self.loaded.read().unwrap_or_else(|e| e.into_inner());
+        authenticate(&loaded.keys, secret)
+    }
+
+    fn reload_if_changed(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        {
+            let loaded = self.loaded.read().unwrap_or_else(|e| e.into_inner());
+            if loaded.modified == modified {
+                return;
+            }
+        }
+
+        // A broken or half-written file keeps the previous keys active.
+        match load(path) {
+            Ok(fresh) => {
+                info!(
+                    "🔑 Reloaded {} API key(s) from {}",
+                    fresh.keys.len(),
+                    path.display()
+                );
+                *self.loaded.write().unwrap_or_else(|e| e.into_inner()) = fresh;
+            }
+            Err(err) => {
+                warn!("keeping previous API keys: {}", err);
+                self.loaded
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .modified = modified;
+            }
+        }
+    }
+}
+
+/// Finds the unexpired key in `keys` matching `secret`.
+pub fn authenticate(keys: &[ApiKey], secret: &str) -> Option<Principal> {
+    let now = Utc::now();
+    // Compare against every key so the timing does not reveal which one matched.
+    let mut matched = None;
+    for key in keys {
+        if key.key.verify(secret) && !key.is_expired(now) {
+            matched = Some(Principal::new(&key.name, key.scopes.clone()));
+        }
+    }
+    matched
+}
+
+/// Logs expired and plaintext keys.
+pub fn warn_about(keys: &[ApiKey]) {
+    let now = Utc::now();
+    for key in keys {
+        if key.is_expired(now) {
+            warn!("API key `{}` has expired", key.name);
+        }
+        if !key.key.is_hashed() {
+            warn!(
+                "API key `{}` is stored in plaintext; replace it with the output of `agent hash-key`",
+                key.name
+            );
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Loaded, KeyFileError> {
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let content = fs::read_to_string(path).map_err(|err| KeyFileError::Io(path.into(), err))?;
+    let file: KeyFile =
+        serde_json::from_str(&content).map_err(|err| KeyFileError::Parse(path.into(), err))?;
+
+    let mut names: Vec<&str> = file.keys.iter().map(|key| key.name.as_str()).collect();
+    names.sort_unstable();
+    if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(KeyFileError::DuplicateName(
+            path.into(),
+            pair[0].to_string(),
+        ));
+    }
+
+    warn_about(&file.keys);
+
+    Ok(Loaded {
+        keys: file.keys,
+        modified,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str, secret: &str, scopes: Vec<Scope>) -> ApiKey {
+        ApiKey {
+            name: name.to_string(),
+            key: secret.parse().unwrap(),
+            scopes,
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn parses_plain_and_hashed_secrets() {
+        let plain: Secret = "hunter2".parse().unwrap();
+        assert_eq!(plain, Secret::Plain("hunter2".to_string()));
+        assert!(!plain.is_hashed());
+
+        let digest = "ab".repeat(32);
+        let hashed: Secret = format!("sha256$0011${digest}").parse().unwrap();
+        assert_eq!(
+            hashed,
+            Secret::Sha256 {
+                salt: vec![0x00, 0x11],
+                digest: vec![0xab; 32],
+            }
+        );
+        assert!(hashed.is_hashed());
+    }
+
+    #[test]
+    fn rejects_malformed_hashes() {
+        let digest = "ab".repeat(32);
+        for value in [
+            "sha256$".to_string(),
+            format!("sha256${digest}"),
+            format!("sha256$${digest}"),
+            format!("sha256$0g11${digest}"),
+            format!("sha256$011${digest}"),
+            "sha256$0011$abcd".to_string(),
+            format!("sha256$0011${digest}ab"),
+        ] {
+            assert!(value.parse::<Secret>().is_err(), "{value} was accepted");
+        }
+    }
+
+    #[test]
+    fn hashed_secrets_verify_only_their_key() {
+        let entry = Secret::hash("correct horse");
+        assert!(entry.starts_with("sha256$"));
+        assert_ne!(entry, Secret::hash("correct horse"), "salt is not random");
+
+        let secret: Secret = entry.parse().unwrap();
+        assert!(secret.verify("correct horse"));
+        assert!(!secret.verify("correct horse "));
+        assert!(!secret.verify(""));
+        assert!(!secret.verify(&entry));
+
+        let plain = Secret::Plain("correct horse".to_string());
+        assert!(plain.verify("correct horse"));
+        assert!(!plain.verify("correct"));
+    }
+
+    #[test]
+    fn debug_output_redacts_secrets() {
+        let secret: Secret = "hunter2".parse().unwrap();
+        assert!(!format!("{secret:?}").contains("hunter2"));
+    }
+
+    #[test]
+    fn admin_implies_every_scope() {
+        let reader = Principal::new("reader", vec![Scope::ReadMetrics]);
+        assert!(reader.allows(Scope::ReadMetrics));
+        assert!(!reader.allows(Scope::ReadProcesses));
+        assert!(!reader.allows(Scope::Admin));
+
+        let admin = Principal::new("admin", vec![Scope::Admin]);
+        for scope in [Scope::ReadMetrics, Scope::ReadProcesses, Scope::Admin] {
+            assert!(admin.allows(scope));
+        }
+
+        assert!(!Principal::new("none", Vec::new()).allows(Scope::ReadMetrics));
+    }
+
+    #[test]
+    fn parses_scopes() {
+        for scope in [Scope::ReadMetrics, Scope::ReadProcesses, Scope::Admin] {
+            assert_eq!(scope.as_str().parse::<Scope>(), Ok(scope));
+        }
+        assert!("Admin".parse::<Scope>().is_err());
+        assert!("write-metrics".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn authenticates_matching_unexpired_keys() {
+        let mut expired = key("old", "old-secret", vec![Scope::Admin]);
+        expired.expires_at = Some(Utc::now() - chrono::Duration::seconds(1));
+        let mut current = key("ops", &Secret::hash("ops-secret"), vec![Scope::Admin]);
+        current.expires_at = Some(Utc::now() + chrono::Duration::hours(1));
+        let keys = [
+            key("reader", "read-secret", vec![Scope::ReadMetrics]),
+            expired,
+            current,
+        ];
+
+        let reader = authenticate(&keys, "read-secret").unwrap();
+        assert_eq!(reader.name, "reader");
+        assert_eq!(reader.scopes, vec![Scope::ReadMetrics]);
+        assert_eq!(authenticate(&keys, "ops-secret").unwrap().name, "ops");
+        assert!(authenticate(&keys, "old-secret").is_none());
+        assert!(authenticate(&keys, "wrong").is_none());
+        assert!(authenticate(&[], "read-secret").is_none());
+    }
+}
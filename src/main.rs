@@ -1,25 +1,86 @@
+mod access;
 mod api;
+mod audit;
 mod auth;
+mod catalog;
+mod cli;
 mod collector;
 mod config;
+mod grafana;
+mod health;
+mod keys;
+mod openapi;
+mod ratelimit;
+mod reload;
 mod scheduler;
+mod series;
+mod shutdown;
 mod state;
 mod storage;
+mod stream;
+mod tls;
+mod tokens;
 mod ui;
+mod ws;
 
+use crate::cli::{Cli, Command};
 use crate::config::Config;
 use crate::state::SharedState;
-use axum::{routing::get, Router};
-use std::sync::Arc;
+use axum::{middleware, routing::get, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use clap::Parser;
+use std::{net::SocketAddr, path::Path, process::ExitCode, sync::Arc};
 use tokio::net::TcpListener;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Load environment variables from `.env` when available.
+async fn main() -> ExitCode {
+    // Load environment variables from `.env` when available, so CONFIG_FILE can
+    // come from there too.
     let _ = dotenvy::dotenv();
+    // An empty CONFIG_FILE means no file, not a missing --config value.
+    if std::env::var_os("CONFIG_FILE").is_some_and(|path| path.is_empty()) {
+        std::env::remove_var("CONFIG_FILE");
+    }
+    let cli = Cli::parse();
+    let config_file = cli.config;
+    let command = cli.command.unwrap_or(Command::Run);
 
+    if !matches!(command, Command::Run) {
+        // Keep stdout for the command's output; only warnings go to stderr.
+        tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::new("warn"))
+            .with_writer(std::io::stderr)
+            .with_target(false)
+            .compact()
+            .init();
+    }
+
+    let result = match command {
+        Command::Run => run(config_file.as_deref()).await,
+        Command::Snapshot { format } => cli::snapshot(config_file.as_deref(), format).await,
+        Command::CheckConfig { path } => cli::check_config(path.or(config_file)),
+        Command::Export(args) => cli::export(config_file.as_deref(), args).await,
+        Command::Import { file } => cli::import(config_file.as_deref(), &file).await,
+        Command::Version => {
+            cli::version();
+            Ok(())
+        }
+        Command::HashKey { key } => cli::hash_key(key),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("✗ {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Collects snapshots in the background and serves the API, UI and Grafana
+/// datasource until the process is stopped.
+async fn run(config_file: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
     // Initialise tracing with sensible defaults.
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -30,12 +91,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .compact()
         .init();
 
-    let config = Config::from_env()?;
+    let config = Config::load(config_file)?;
+    if let Some(path) = config.config_file() {
+        info!("⚙️  Configuration loaded from {}", path.display());
+    }
+    if config.api_key().is_some_and(|key| !key.is_hashed()) {
+        warn!(
+            "SYSTEM_API_KEY is stored in plaintext; replace it with the output of `agent hash-key`"
+        );
+    }
+    keys::warn_about(config.api_keys());
+    let keys = keys::KeyStore::open(config.api_keys_file())?;
+    // Held until the process exits, so `agent import` cannot write meanwhile.
+    let _storage_lock = storage::lock(&config)?;
 
     // Perform an initial collection so API/UI have data immediately.
     let initial_snapshot = tokio::task::spawn_blocking({
         let interval = config.collection_interval();
-        move || collector::collect_snapshot(None, interval)
+        let settings = config.collector().clone();
+        move || collector::collect_snapshot(None, interval, &settings)
     })
     .await
     .map_err(|err| {
@@ -43,28 +117,93 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         err
     })?;
 
-    let state: SharedState = Arc::new(state::AppState::new(config.clone(), initial_snapshot));
+    let state: SharedState = Arc::new(state::AppState::new(config.clone(), keys, initial_snapshot));
 
-    scheduler::spawn(state.clone());
+    let scheduler = scheduler::spawn(state.clone());
+    reload::spawn_signal_handler(state.clone());
 
-    let app = Router::new()
-        .route("/api/system", get(api::system))
-        .route("/api/history", get(api::history))
-        .route("/api/snapshots", get(api::snapshot_file))
-        .route("/api/apps", get(api::apps))
-        .route("/api/tasks", get(api::tasks))
-        .route("/api/webtest", get(api::webtest))
-        .route("/api/alerts", get(api::alerts))
-        .route("/ui", get(ui::show_ui))
-        .with_state(state.clone());
+    let app = app(state.clone());
 
     let bind_addr = config.bind_address().to_string();
-    let listener = TcpListener::bind(&bind_addr).await?;
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    let server = if let Some(tls) = config.tls() {
+        let rustls = RustlsConfig::from_config(tls::server_config(tls)?);
+        tls::spawn_reload(tls.clone(), rustls.clone());
+
+        let listener = std::net::TcpListener::bind(&bind_addr)?;
+        listener.set_nonblocking(true)?;
 
-    info!("🚀 API: http://{}/api/system", bind_addr);
-    info!("🖥️  UI: http://{}/ui", bind_addr);
+        info!("🚀 API: https://{}/api/system", bind_addr);
+        info!("🖥️  UI: https://{}/ui", bind_addr);
+        if tls.client_ca.is_some() {
+            info!("🔐 Client certificates are verified against the configured CA");
+        }
 
-    axum::serve(listener, app).await?;
+        let handle = axum_server::Handle::new();
+        tokio::spawn({
+            let handle = handle.clone();
+            let stopping = state.shutdown_requested();
+            async move {
+                stopping.await;
+                handle.graceful_shutdown(None);
+            }
+        });
+        let server = axum_server::from_tcp(listener)
+            .acceptor(tls::ClientCertAcceptor::new(rustls))
+            .handle(handle)
+            .serve(app);
+        tokio::spawn(server)
+    } else {
+        let listener = TcpListener::bind(&bind_addr).await?;
+
+        info!("🚀 API: http://{}/api/system", bind_addr);
+        info!("🖥️  UI: http://{}/ui", bind_addr);
+
+        let stopping = state.shutdown_requested();
+        tokio::spawn(async move {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(stopping)
+                .await
+        })
+    };
+
+    shutdown::wait(state, server, scheduler).await
+}
 
-    Ok(())
+/// The API, UI and Grafana routes behind the access filter, rate limiter,
+/// authentication and audit log.
+fn app(state: SharedState) -> Router {
+    Router::new()
+        .nest(api::V1_PREFIX, api::router())
+        // Unversioned aliases for existing clients.
+        .nest("/api", api::router())
+        .merge(ui::router())
+        .nest("/grafana", grafana::router())
+        // Grafana probes the datasource URL with a trailing slash.
+        .route("/grafana/", get(grafana::health).post(grafana::health))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .fallback(api::not_found)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::authenticate,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            tokens::restrict,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::limit,
+        ))
+        // Rejected addresses never reach rate limiting or key checks.
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            access::filter,
+        ))
+        // Outermost, so rejected and throttled requests are recorded too. The
+        // caller is only resolved by `api::authenticate`, once they are let through.
+        .layer(middleware::from_fn_with_state(state.clone(), audit::record))
+        .with_state(state)
 }
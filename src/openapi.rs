@@ -0,0 +1,112 @@
+//! OpenAPI 3 description of the `/api/v1` routes, generated from the handler
+//! annotations and the response types.
+
+use axum::Json;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    api, audit, catalog, collector, config, health, keys, reload, storage, stream, tokens, ws,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "System Monitoring Agent API",
+        description = "System metrics, history and process details collected by the agent.",
+        license(name = "MIT"),
+    ),
+    paths(
+        api::system,
+        api::history,
+        api::snapshot_file,
+        api::series,
+        api::metric_catalog,
+        api::process,
+        api::apps,
+        api::tasks,
+        api::webtest,
+        api::alerts,
+        stream::stream,
+        ws::upgrade,
+        audit::audit,
+        tokens::issue,
+        health::status,
+        health::metrics,
+        reload::reload,
+    ),
+    components(schemas(
+        collector::SystemSnapshot,
+        collector::DiskUsage,
+        collector::NetworkInterfaceUsage,
+        collector::ProcessInfo,
+        collector::ProcessDetails,
+        collector::ProcessRef,
+        collector::GpuInfo,
+        catalog::CatalogEntry,
+        catalog::MetricDescriptor,
+        catalog::MetricKind,
+        storage::SnapshotHistory,
+        api::SeriesResponse,
+        api::CatalogResponse,
+        api::ProcessResponse,
+        api::ProcessSample,
+        api::TopListSpan,
+        api::AlertsResponse,
+        api::ErrorResponse,
+        api::ErrorDetail,
+        audit::AuditResponse,
+        audit::AuditRecord,
+        audit::AuthOutcome,
+        keys::Scope,
+        tokens::TokenRequest,
+        tokens::TokenResponse,
+        collector::AgentMetrics,
+        collector::SectionMetrics,
+        health::AgentStatus,
+        health::SelfMetrics,
+        health::CollectionStatus,
+        health::Timing,
+        health::SourceStatus,
+        health::StorageStatus,
+        health::ProcessUsage,
+        health::AuditStatus,
+        health::ReloadStatus,
+        health::AlertEvent,
+        health::AlertState,
+        reload::ReloadResponse,
+        config::ConfigSummary,
+    )),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "metrics", description = "Snapshots, history and time series"),
+        (name = "processes", description = "Process details (scope `read-processes`)"),
+        (name = "streaming", description = "Live updates"),
+        (name = "auth", description = "Short-lived tokens"),
+        (name = "admin", description = "Requires the `admin` scope"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::ApiKey(ApiKey::Query(ApiKeyValue::new("token"))),
+        );
+    }
+}
+
+/// Serves the document. It only describes the API, so no key is required.
+pub async fn spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::warn;
+
+use crate::{access::ClientIp, api::ApiError, config::RateLimits, state::SharedState};
+
+// Endpoints that may read large amounts of stored history share one global
+// budget, charged by `api::authenticate` once the caller is known.
+const HISTORY_PATHS: &[&str] = &[
+    "/api/history",
+    "/api/snapshots",
+    "/api/series",
+    "/grafana/query",
+];
+
+// Idle clients are forgotten once the table grows beyond this size.
+const MAX_TRACKED_CLIENTS: usize = 4096;
+
+/// Whether `path` (without the version prefix) reads stored history.
+pub fn is_history_path(path: &str) -> bool {
+    HISTORY_PATHS.contains(&path)
+}
+
+fn is_exempt(ip: IpAddr, limits: &RateLimits) -> bool {
+    limits.exempt.iter().any(|net| net.contains(&ip))
+}
+
+/// Marks a response as a failed login, for handlers that answer bad
+/// credentials with something other than `401` (e.g. a redirect).
+#[derive(Debug, Clone, Copy)]
+pub struct AuthFailure;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(capacity: f64) -> Self {
+        Self {
+            tokens: capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes one token, or returns how long until one is available.
+    fn take(&mut self, per_minute: u32) -> Result<(), Duration> {
+        let capacity = f64::from(per_minute);
+        let per_second = capacity / 60.0;
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(capacity);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / per_second))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Client {
+    bucket: Option<Bucket>,
+    failures: Vec<Instant>,
+    locked_until: Option<Instant>,
+}
+
+impl Client {
+    fn is_idle(&self, now: Instant, lockout: Duration) -> bool {
+        self.locked_until.is_none_or(|until| until <= now)
+            && self
+                .failures
+                .last()
+                .is_none_or(|last| now.duration_since(*last) > lockout)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    clients: Mutex<HashMap<IpAddr, Client>>,
+    history: Mutex<Option<Bucket>>,
+}
+
+impl RateLimiter {
+    fn check(&self, ip: IpAddr, limits: &RateLimits) -> Result<(), Duration> {
+        let now = Instant::now();
+        {
+            let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+            if clients.len() > MAX_TRACKED_CLIENTS {
+                clients.retain(|_, client| !client.is_idle(now, limits.lockout));
+            }
+            let client = clients.entry(ip).or_default();
+
+            if let Some(until) = client.locked_until.filter(|until| *until > now) {
+                return Err(until - now);
+            }
+            if limits.per_client_per_minute > 0 {
+                client
+                    .bucket
+                    .get_or_insert_with(|| Bucket::full(f64::from(limits.per_client_per_minute)))
+                    .take(limits.per_client_per_minute)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Charges a history read to the budget shared by the history endpoints
+    /// and WebSocket `history` requests. Exempt addresses are not charged.
+    pub fn check_history(&self, ip: Option<IpAddr>, limits: &RateLimits) -> Result<(), Duration> {
+        if ip.is_some_and(|ip| is_exempt(ip, limits)) {
+            return Ok(());
+        }
+        self.take_history(limits)
+    }
+
+    fn take_history(&self, limits: &RateLimits) -> Result<(), Duration> {
+        if limits.history_per_minute == 0 {
+            return Ok(());
+        }
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        history
+            .get_or_insert_with(|| Bucket::full(f64::from(limits.history_per_minute)))
+            .take(limits.history_per_minute)
+    }
+
+    /// Records a failed authentication and locks the client out once it
+    /// reaches the configured number of failures within the lockout period.
+    fn record_failure(&self, ip: IpAddr, limits: &RateLimits) {
+        if limits.auth_failure_limit == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let client = clients.entry(ip).or_default();
+        client
+            .failures
+            .retain(|failure| now.duration_since(*failure) <= limits.lockout);
+        client.failures.push(now);
+
+        if client.failures.len() >= limits.auth_failure_limit as usize {
+            warn!(
+                "🔒 {} locked out for {}s after {} failed authentication attempts",
+                ip,
+                limits.lockout.as_secs(),
+                client.failures.len()
+            );
+            client.failures.clear();
+            client.locked_until = Some(now + limits.lockout);
+        }
+    }
+}
+
+/// Middleware enforcing the per-client limit and the lockout after repeated
+/// authentication failures.
+pub async fn limit(State(state): State<SharedState>, request: Request, next: Next) -> Response {
+    let config = state.config();
+    let limits = config.rate_limits();
+    let Some(&ClientIp(ip)) = request.extensions().get::<ClientIp>() else {
+        return next.run(request).await;
+    };
+    if is_exempt(ip, limits) {
+        return next.run(request).await;
+    }
+
+    if let Err(retry_after) = state.rate_limiter().check(ip, limits) {
+        return ApiError::TooManyRequests(retry_after).into_response();
+    }
+
+    let response = next.run(request).await;
+    if response.status() == StatusCode::UNAUTHORIZED
+        || response.extensions().get::<AuthFailure>().is_some()
+    {
+        state.rate_limiter().record_failure(ip, limits);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> RateLimits {
+        RateLimits {
+            per_client_per_minute: 0,
+            history_per_minute: 0,
+            auth_failure_limit: 0,
+            lockout: Duration::from_secs(60),
+            exempt: vec!["10.0.0.0/8".parse().unwrap()],
+        }
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn locks_out_after_repeated_failures() {
+        let limits = RateLimits {
+            auth_failure_limit: 3,
+            ..limits()
+        };
+        let limiter = RateLimiter::default();
+        let client = ip("192.0.2.1");
+
+        limiter.record_failure(client, &limits);
+        limiter.record_failure(client, &limits);
+        assert!(limiter.check(client, &limits).is_ok());
+
+        limiter.record_failure(client, &limits);
+        let retry_after = limiter.check(client, &limits).unwrap_err();
+        assert!(retry_after <= limits.lockout);
+        assert!(retry_after > Duration::from_secs(59));
+
+        // Other clients are unaffected.
+        assert!(limiter.check(ip("192.0.2.2"), &limits).is_ok());
+    }
+
+    #[test]
+    fn failures_are_not_counted_without_a_limit() {
+        let limits = limits();
+        let limiter = RateLimiter::default();
+        let client = ip("192.0.2.1");
+        for _ in 0..100 {
+            limiter.record_failure(client, &limits);
+        }
+        assert!(limiter.check(client, &limits).is_ok());
+    }
+
+    #[test]
+    fn limits_requests_per_client() {
+        let limits = RateLimits {
+            per_client_per_minute: 2,
+            ..limits()
+        };
+        let limiter = RateLimiter::default();
+        let client = ip("192.0.2.1");
+
+        assert!(limiter.check(client, &limits).is_ok());
+        assert!(limiter.check(client, &limits).is_ok());
+        let retry_after = limiter.check(client, &limits).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(30));
+        assert!(limiter.check(ip("192.0.2.2"), &limits).is_ok());
+    }
+
+    #[test]
+    fn exempt_addresses_do_not_spend_the_history_budget() {
+        let limits = RateLimits {
+            history_per_minute: 1,
+            ..limits()
+        };
+        assert!(is_exempt(ip("10.1.2.3"), &limits));
+        assert!(!is_exempt(ip("11.1.2.3"), &limits));
+
+        let limiter = RateLimiter::default();
+        for _ in 0..5 {
+            assert!(limiter.check_history(Some(ip("10.1.2.3")), &limits).is_ok());
+        }
+        assert!(limiter
+            .check_history(Some(ip("192.0.2.1")), &limits)
+            .is_ok());
+        // The budget is global, so it is spent for every other caller.
+        assert!(limiter
+            .check_history(Some(ip("192.0.2.2")), &limits)
+            .is_err());
+        assert!(limiter.check_history(None, &limits).is_err());
+        assert!(limiter.check_history(Some(ip("10.1.2.3")), &limits).is_ok());
+    }
+
+    #[test]
+    fn recognises_history_paths() {
+        assert!(is_history_path("/api/history"));
+        assert!(is_history_path("/grafana/query"));
+        assert!(!is_history_path("/api/metrics"));
+        assert!(!is_history_path("/api/history/extra"));
+    }
+}
@@ -0,0 +1,96 @@
+//! Configuration reload on SIGHUP and via `POST /api/config/reload`.
+
+use axum::{extract::State, Json};
+use serde::Serialize;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    api::{ApiError, Caller},
+    config::ConfigSummary,
+    keys::Scope,
+    state::{ReloadError, Reloaded, SharedState},
+};
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReloadResponse {
+    /// Changed settings that only take effect after a restart.
+    pub restart_required: Vec<&'static str>,
+    /// Settings fixed by environment variables, including those from `.env`,
+    /// which is only read at startup. The config file cannot change them.
+    pub fixed_by_environment: Vec<&'static str>,
+    pub config: ConfigSummary,
+}
+
+/// Reloads the configuration, logs the outcome and records it for the status
+/// report.
+fn apply(state: &SharedState, trigger: &str) -> Result<Reloaded, ReloadError> {
+    let result = state.reload_config();
+    match &result {
+        Ok(reloaded) => {
+            info!("🔄 Configuration reloaded ({trigger})");
+            if !reloaded.fixed_by_environment.is_empty() {
+                warn!(
+                    "set in the environment or .env, not reloaded: {}",
+                    reloaded.fixed_by_environment.join(", ")
+                );
+            }
+        }
+        Err(err) => {
+            error!("configuration reload ({trigger}) rejected, keeping the current one: {err}")
+        }
+    }
+    state
+        .health()
+        .record_reload(result.as_ref().err().map(ToString::to_string));
+    result
+}
+
+/// Reloads the configuration whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub fn spawn_signal_handler(state: SharedState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            error!("cannot listen for SIGHUP, reload via the API only: {err}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            let _ = apply(&state, "SIGHUP");
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_signal_handler(_state: SharedState) {}
+
+/// Re-reads the configuration file and environment. An invalid configuration
+/// is rejected and the running one stays in place.
+#[utoipa::path(
+    post,
+    path = "/api/v1/config/reload",
+    tag = "admin",
+    responses(
+        (status = 200, body = ReloadResponse),
+        (status = 401, body = api::ErrorResponse),
+        (status = 403, body = api::ErrorResponse),
+        (status = 422, description = "New configuration is invalid", body = api::ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn reload(
+    State(state): State<SharedState>,
+    caller: Caller,
+) -> Result<Json<ReloadResponse>, ApiError> {
+    let principal = caller.authorise(Scope::Admin)?;
+    let reloaded = apply(&state, &format!("requested by {}", principal.name))?;
+    Ok(Json(ReloadResponse {
+        restart_required: reloaded.restart_required,
+        fixed_by_environment: reloaded.fixed_by_environment,
+        config: state.config().summary(),
+    }))
+}
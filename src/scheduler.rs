@@ -1,5 +1,7 @@
-use tokio::time::sleep;
-use tracing::{error, warn};
+use std::time::Instant;
+
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     collector::{self, SystemSnapshot},
@@ -7,39 +9,71 @@ use crate::{
     storage,
 };
 
-pub fn spawn(state: SharedState) {
-    let config = state.config().clone();
-
+/// Collects and stores a snapshot every interval. The task ends after the
+/// current round (collection and write) once shutdown is requested.
+pub fn spawn(state: SharedState) -> JoinHandle<()> {
     tokio::spawn(async move {
         let mut previous: Option<SystemSnapshot> = None;
-        let interval = config.collection_interval();
 
         loop {
+            // Read on every round so a reloaded configuration applies.
+            let config = state.config();
+            let interval = config.collection_interval();
             let prev_for_collect = previous.clone();
             let interval_copy = interval;
+            let settings = config.collector().clone();
 
+            let started = Instant::now();
             let collection_result = tokio::task::spawn_blocking(move || {
-                collector::collect_snapshot(prev_for_collect, interval_copy)
+                collector::collect_snapshot_with_report(prev_for_collect, interval_copy, &settings)
             })
             .await;
 
             let snapshot = match collection_result {
-                Ok(snapshot) => snapshot,
+                Ok((mut snapshot, report)) => {
+                    for err in &report.errors {
+                        debug!("{} collection failed: {}", err.source, err.message);
+                    }
+                    let elapsed = started.elapsed();
+                    if elapsed > interval {
+                        debug!(
+                            "collection took {:.0} ms, longer than the {:.0} ms interval",
+                            elapsed.as_secs_f64() * 1000.0,
+                            interval.as_secs_f64() * 1000.0
+                        );
+                    }
+                    let metrics = state.health().record_collection(elapsed, interval, &report);
+                    snapshot.agent = Some(metrics);
+                    snapshot
+                }
                 Err(err) => {
                     error!("collector worker failed: {}", err);
-                    sleep(interval).await;
-                    continue;
+                    state.health().record_collector_failure();
+                    tokio::select! {
+                        _ = sleep(interval) => continue,
+                        _ = state.shutdown_requested() => break,
+                    }
                 }
             };
 
-            if let Err(err) = storage::save_snapshot(&snapshot, &config).await {
+            let started = Instant::now();
+            let saved = storage::save_snapshot(&snapshot, &config).await;
+            if let Err(err) = &saved {
                 warn!("could not persist snapshot: {}", err);
             }
+            state
+                .health()
+                .record_storage_write(started.elapsed(), saved.as_ref().err());
 
             state.record_snapshot(snapshot.clone()).await;
             previous = Some(snapshot);
 
-            sleep(interval).await;
+            tokio::select! {
+                _ = sleep(interval) => {}
+                _ = state.collection_requested() => {}
+                _ = state.shutdown_requested() => break,
+            }
         }
-    });
+        info!("⏹️  Scheduler stopped");
+    })
 }
@@ -0,0 +1,293 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::collector::SystemSnapshot;
+
+/// Fields used to address array elements by name instead of position,
+/// e.g. `disks[/]` (mount point), `network[eth0]` or `top_processes[1234]`.
+/// The catalog names its placeholders after the first one an element has.
+pub(crate) const IDENTITY_FIELDS: [&str; 4] = ["mount_point", "pid", "index", "name"];
+
+#[derive(Debug, Error)]
+pub enum SeriesError {
+    #[error("invalid metric path: {0}")]
+    Path(String),
+    #[error("invalid step: {0}")]
+    Step(String),
+    #[error("unknown aggregation: {0}")]
+    Aggregation(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    field: String,
+    selector: Option<String>,
+}
+
+/// A dotted path into a [`SystemSnapshot`], such as `disks[/].used_pct`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricPath {
+    raw: String,
+    segments: Vec<Segment>,
+}
+
+impl MetricPath {
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// Resolves the path against a snapshot that has already been converted to JSON.
+    pub fn resolve_value(&self, snapshot: &Value) -> Option<f64> {
+        self.resolve_json(snapshot)?.as_f64()
+    }
+
+    /// Like [`MetricPath::resolve_value`], but returns the field whatever its type.
+    pub fn resolve_json<'a>(&self, snapshot: &'a Value) -> Option<&'a Value> {
+        let mut current = snapshot;
+        for segment in &self.segments {
+            current = current.get(&segment.field)?;
+            if let Some(selector) = &segment.selector {
+                current = select_element(current.as_array()?, selector)?;
+            }
+        }
+        Some(current)
+    }
+
+    pub fn resolve(&self, snapshot: &SystemSnapshot) -> Option<f64> {
+        let value = serde_json::to_value(snapshot).ok()?;
+        self.resolve_value(&value)
+    }
+}
+
+impl FromStr for MetricPath {
+    type Err = SeriesError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let invalid = || SeriesError::Path(raw.to_string());
+        let mut segments = Vec::new();
+        let mut chars = raw.trim().chars().peekable();
+
+        loop {
+            let mut field = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == '.' || c == '[' {
+                    break;
+                }
+                field.push(c);
+                chars.next();
+            }
+            if field.is_empty() {
+                return Err(invalid());
+            }
+
+            let mut selector = None;
+            if chars.peek() == Some(&'[') {
+                chars.next();
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err(invalid()),
+                    }
+                }
+                if inner.is_empty() {
+                    return Err(invalid());
+                }
+                selector = Some(inner);
+            }
+
+            segments.push(Segment { field, selector });
+
+            match chars.next() {
+                None => break,
+                Some('.') => continue,
+                Some(_) => return Err(invalid()),
+            }
+        }
+
+        Ok(Self {
+            raw: raw.trim().to_string(),
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for MetricPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+fn select_element<'a>(items: &'a [Value], selector: &str) -> Option<&'a Value> {
+    let by_identity = items.iter().find(|item| {
+        IDENTITY_FIELDS.iter().any(|key| match item.get(*key) {
+            Some(Value::String(s)) => s == selector,
+            Some(Value::Number(n)) => n.to_string() == selector,
+            _ => false,
+        })
+    });
+
+    by_identity.or_else(|| {
+        let position: usize = selector.parse().ok()?;
+        items.get(position)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+    P50,
+    P95,
+    P99,
+    Last,
+}
+
+impl Aggregation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aggregation::Avg => "avg",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+            Aggregation::P50 => "p50",
+            Aggregation::P95 => "p95",
+            Aggregation::P99 => "p99",
+            Aggregation::Last => "last",
+        }
+    }
+
+    /// Reduces one bucket of values, which are given in timestamp order.
+    fn apply(&self, values: &[f64]) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        let value = match self {
+            Aggregation::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregation::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregation::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Aggregation::P50 => percentile(values, 50.0),
+            Aggregation::P95 => percentile(values, 95.0),
+            Aggregation::P99 => percentile(values, 99.0),
+            Aggregation::Last => *values.last()?,
+        };
+        Some(value)
+    }
+}
+
+impl FromStr for Aggregation {
+    type Err = SeriesError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "avg" | "mean" => Ok(Aggregation::Avg),
+            "min" => Ok(Aggregation::Min),
+            "max" => Ok(Aggregation::Max),
+            "p50" | "median" => Ok(Aggregation::P50),
+            "p95" => Ok(Aggregation::P95),
+            "p99" => Ok(Aggregation::P99),
+            "last" => Ok(Aggregation::Last),
+            _ => Err(SeriesError::Aggregation(raw.to_string())),
+        }
+    }
+}
+
+/// Nearest-rank percentile.
+fn percentile(values: &[f64], pct: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Parses steps like `500ms`, `30s`, `5m`, `1h`, `1d` or a bare number of seconds.
+pub fn parse_step(raw: &str) -> Result<Duration, SeriesError> {
+    let invalid = || SeriesError::Step(raw.to_string());
+    let trimmed = raw.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        "d" => number * 86_400.0,
+        _ => return Err(invalid()),
+    };
+
+    if seconds <= 0.0 {
+        return Err(invalid());
+    }
+    // Rejects steps too large for a `Duration` (and NaN/infinity) instead of panicking.
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// Extracts `[timestamp, value]` points for `path`, optionally reduced per `step`
+/// bucket. Snapshots must be ordered by ascending timestamp; snapshots that do
+/// not contain the metric are skipped.
+pub fn build_series(
+    snapshots: &[SystemSnapshot],
+    path: &MetricPath,
+    step: Option<Duration>,
+    aggregation: Aggregation,
+) -> Vec<(i64, f64)> {
+    let raw = snapshots
+        .iter()
+        .filter_map(|snapshot| path.resolve(snapshot).map(|v| (snapshot.timestamp, v)));
+
+    let Some(step) = step else {
+        return raw.collect();
+    };
+
+    let step_ms = i64::try_from(step.as_millis()).unwrap_or(i64::MAX).max(1);
+    let mut points = Vec::new();
+    let mut bucket_start: Option<i64> = None;
+    let mut bucket: Vec<f64> = Vec::new();
+
+    for (ts, value) in raw {
+        let start = ts - ts.rem_euclid(step_ms);
+        if bucket_start != Some(start) {
+            if let Some(previous) = bucket_start {
+                points.extend(aggregation.apply(&bucket).map(|v| (previous, v)));
+            }
+            bucket.clear();
+            bucket_start = Some(start);
+        }
+        bucket.push(value);
+    }
+    if let Some(previous) = bucket_start {
+        points.extend(aggregation.apply(&bucket).map(|v| (previous, v)));
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_steps_with_units() {
+        assert_eq!(parse_step("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_step("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_step("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_step("1d").unwrap(), Duration::from_secs(86_400));
+    }
+
+    #[test]
+    fn rejects_zero_and_oversized_steps() {
+        for raw in ["0", "0s", "0.0h", "99999999999999999999999d", "1e400", "5w"] {
+            assert!(
+                matches!(parse_step(raw), Err(SeriesError::Step(_))),
+                "{raw} was accepted"
+            );
+        }
+    }
+}
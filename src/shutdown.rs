@@ -0,0 +1,95 @@
+//! Graceful shutdown on SIGINT and SIGTERM.
+
+use std::{error::Error, io};
+
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::state::SharedState;
+
+/// Resolves on the first SIGINT (Ctrl+C) or SIGTERM and returns its name.
+async fn signal() -> &'static str {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!("cannot listen for SIGINT: {err}");
+            std::future::pending::<()>().await;
+        }
+        "SIGINT"
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                error!("cannot listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+        "SIGTERM"
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<&'static str>();
+
+    tokio::select! {
+        name = interrupt => name,
+        name = terminate => name,
+    }
+}
+
+/// Runs until a shutdown signal arrives, then stops accepting connections and
+/// waits for in-flight requests, the scheduler's current round and the audit
+/// log. The process exits with status 1 when that takes longer than
+/// `SHUTDOWN_TIMEOUT_SECS` or a second signal arrives.
+pub async fn wait(
+    state: SharedState,
+    mut server: JoinHandle<io::Result<()>>,
+    scheduler: JoinHandle<()>,
+) -> Result<(), Box<dyn Error>> {
+    let received = tokio::select! {
+        // The server only returns on its own when it fails.
+        served = &mut server => return Ok(served??),
+        received = signal() => received,
+    };
+
+    let deadline = state.config().shutdown_timeout();
+    info!(
+        "🛑 {received} received, shutting down (at most {}s)",
+        deadline.as_secs()
+    );
+    state.shutdown();
+
+    let drained = async {
+        let served = server.await;
+        if scheduler.await.is_err() {
+            warn!("scheduler task failed during shutdown");
+        }
+        state.audit().close().await;
+        served
+    };
+
+    tokio::select! {
+        drained = tokio::time::timeout(deadline, drained) => match drained {
+            Ok(served) => {
+                served??;
+                info!("👋 Shutdown complete");
+                Ok(())
+            }
+            Err(_) => {
+                error!(
+                    "shutdown did not finish within {}s, exiting with requests still open",
+                    deadline.as_secs()
+                );
+                std::process::exit(1);
+            }
+        },
+        signal = signal() => {
+            warn!("{signal} received again, exiting immediately");
+            std::process::exit(1);
+        }
+    }
+}
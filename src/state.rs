@@ -1,30 +1,156 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::{collections::VecDeque, future::Future, sync::Arc};
 
-use tokio::sync::{Mutex, RwLock};
+use thiserror::Error;
+use tokio::sync::{broadcast, watch, Mutex, Notify, RwLock};
+use tracing::warn;
 
-use crate::{collector::SystemSnapshot, config::Config};
+use crate::{
+    audit::AuditLog,
+    auth::SessionStore,
+    collector::SystemSnapshot,
+    config::{self, Config, ConfigError},
+    health::AgentHealth,
+    keys::{KeyFileError, KeyStore},
+    ratelimit::RateLimiter,
+    tokens::TokenSigner,
+};
 
 pub type SharedState = Arc<AppState>;
 
+// Receivers that fall further behind than this skip the oldest snapshots.
+const SNAPSHOT_CHANNEL_CAPACITY: usize = 16;
+
 pub struct AppState {
-    config: Config,
+    // Replaced as a whole on reload; readers keep the `Arc` they got.
+    config: std::sync::RwLock<Arc<Config>>,
     latest_snapshot: RwLock<SystemSnapshot>,
     history: Mutex<VecDeque<SystemSnapshot>>,
+    snapshots: broadcast::Sender<SystemSnapshot>,
+    collection_requested: Notify,
+    sessions: SessionStore,
+    keys: std::sync::RwLock<Arc<KeyStore>>,
+    rate_limiter: RateLimiter,
+    audit: AuditLog,
+    tokens: std::sync::RwLock<Arc<TokenSigner>>,
+    health: AgentHealth,
+    reloading: std::sync::Mutex<()>,
+    shutting_down: watch::Sender<bool>,
+}
+
+/// Outcome of a successful configuration reload.
+#[derive(Debug)]
+pub struct Reloaded {
+    /// Changed settings that only take effect after a restart.
+    pub restart_required: Vec<&'static str>,
+    /// Environment variables that override the file and so cannot be reloaded.
+    pub fixed_by_environment: Vec<&'static str>,
+}
+
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Keys(#[from] KeyFileError),
 }
 
 impl AppState {
-    pub fn new(config: Config, initial_snapshot: SystemSnapshot) -> Self {
+    pub fn new(config: Config, keys: KeyStore, initial_snapshot: SystemSnapshot) -> Self {
         let mut history = VecDeque::new();
         history.push_front(initial_snapshot.clone());
+        let (snapshots, _) = broadcast::channel(SNAPSHOT_CHANNEL_CAPACITY);
+        let audit = AuditLog::start(config.audit());
+        let tokens = TokenSigner::new(config.tokens());
         Self {
-            config,
+            config: std::sync::RwLock::new(Arc::new(config)),
             latest_snapshot: RwLock::new(initial_snapshot),
             history: Mutex::new(history),
+            snapshots,
+            collection_requested: Notify::new(),
+            sessions: SessionStore::default(),
+            keys: std::sync::RwLock::new(Arc::new(keys)),
+            rate_limiter: RateLimiter::default(),
+            audit,
+            tokens: std::sync::RwLock::new(Arc::new(tokens)),
+            health: AgentHealth::default(),
+            reloading: std::sync::Mutex::new(()),
+            shutting_down: watch::Sender::new(false),
+        }
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn sessions(&self) -> &SessionStore {
+        &self.sessions
+    }
+
+    pub fn keys(&self) -> Arc<KeyStore> {
+        self.keys.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub fn tokens(&self) -> Arc<TokenSigner> {
+        self.tokens
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Re-reads the configuration file and environment and swaps in the new
+    /// configuration. Nothing changes when it is invalid. Settings bound at
+    /// startup (listen address, TLS, audit log, snapshot directory) keep their
+    /// old values.
+    pub fn reload_config(&self) -> Result<Reloaded, ReloadError> {
+        let _guard = self.reloading.lock().unwrap_or_else(|e| e.into_inner());
+        let current = self.config();
+        let mut config = Config::load(current.config_file())?;
+        let restart_required = config.keep_startup_settings(&current);
+
+        let keys = if config.api_keys_file() == current.api_keys_file() {
+            None
+        } else {
+            Some(KeyStore::open(config.api_keys_file())?)
+        };
+        // Keep the signer, and with it any random key, unless the key changed.
+        let tokens = (config.tokens().signing_key != current.tokens().signing_key)
+            .then(|| TokenSigner::new(config.tokens()));
+        let interval_changed = config.collection_interval() != current.collection_interval();
+
+        if let Some(keys) = keys {
+            *self.keys.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(keys);
+        }
+        if let Some(tokens) = tokens {
+            *self.tokens.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(tokens);
         }
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+
+        for setting in &restart_required {
+            warn!("{setting} changed; the new value takes effect after a restart");
+        }
+        // Let the scheduler pick up a new interval right away.
+        if interval_changed {
+            self.request_collection();
+        }
+        Ok(Reloaded {
+            restart_required,
+            fixed_by_environment: config::environment_overrides(),
+        })
     }
 
-    pub fn config(&self) -> &Config {
-        &self.config
+    pub fn health(&self) -> &AgentHealth {
+        &self.health
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
     }
 
     pub async fn latest_snapshot(&self) -> SystemSnapshot {
@@ -37,15 +163,72 @@ impl AppState {
             *writer = snapshot.clone();
         }
 
-        let mut history = self.history.lock().await;
-        history.push_front(snapshot);
-        while history.len() > self.config.history_limit() {
-            history.pop_back();
+        {
+            let mut history = self.history.lock().await;
+            history.push_front(snapshot.clone());
+            while history.len() > self.config().history_limit() {
+                history.pop_back();
+            }
         }
+
+        // Sending never blocks; it only fails when nobody is subscribed.
+        let _ = self.snapshots.send(snapshot);
+    }
+
+    /// Asks the scheduler to collect right away instead of waiting for the next tick.
+    pub fn request_collection(&self) {
+        self.collection_requested.notify_one();
+    }
+
+    pub async fn collection_requested(&self) {
+        self.collection_requested.notified().await;
     }
 
-    pub async fn history(&self, limit: usize) -> Vec<SystemSnapshot> {
+    /// Starts a graceful shutdown: the server stops accepting connections,
+    /// streams end and the scheduler stops after its current round.
+    pub fn shutdown(&self) {
+        self.shutting_down.send_replace(true);
+    }
+
+    /// Resolves once [`AppState::shutdown`] has been called, immediately if it
+    /// already was.
+    pub fn shutdown_requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut receiver = self.shutting_down.subscribe();
+        async move {
+            let _ = receiver.wait_for(|stopping| *stopping).await;
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SystemSnapshot> {
+        self.snapshots.subscribe()
+    }
+
+    pub async fn oldest_timestamp(&self) -> Option<i64> {
+        self.history.lock().await.back().map(|s| s.timestamp)
+    }
+
+    /// Returns up to `limit` in-memory snapshots (newest first) within `from..=to`,
+    /// and whether the range starts at or after the oldest in-memory snapshot,
+    /// so that no stored snapshot outside the in-memory window can match.
+    pub async fn history_range(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: usize,
+    ) -> (Vec<SystemSnapshot>, bool) {
         let history = self.history.lock().await;
-        history.iter().take(limit).cloned().collect()
+        let matches: Vec<SystemSnapshot> = history
+            .iter()
+            .filter(|s| from.is_none_or(|from| s.timestamp >= from))
+            .filter(|s| to.is_none_or(|to| s.timestamp <= to))
+            .take(limit)
+            .cloned()
+            .collect();
+
+        let covered = match (from, history.back()) {
+            (Some(from), Some(oldest)) => from >= oldest.timestamp,
+            _ => false,
+        };
+        (matches, covered)
     }
 }
@@ -1,29 +1,73 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{File, OpenOptions, TryLockError},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
+use tracing::warn;
+use utoipa::ToSchema;
 
 use crate::{collector::SystemSnapshot, config::Config};
 
+const TIME_SERIES_DIR: &str = "time_series";
+const TIME_SERIES_PREFIX: &str = "system_snapshot_";
+const ARCHIVE_DIR: &str = "archive";
+const ARCHIVE_PREFIX: &str = "system_snapshots_";
+const ARCHIVE_SUFFIX: &str = ".jsonl.gz";
+const LOCK_FILE: &str = ".lock";
+
 #[derive(Debug, thiserror::Error)]
 pub enum StorageError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Serialization(#[from] serde_json::Error),
+    #[error("storage worker failed: {0}")]
+    Worker(#[from] tokio::task::JoinError),
+    #[error("snapshot directory {0} is in use by a running agent")]
+    Locked(PathBuf),
+}
+
+/// Exclusive use of a snapshot directory, held by the server while it runs and
+/// by `agent import`. Released when dropped or when the process exits.
+#[derive(Debug)]
+pub struct StorageLock {
+    _file: File,
+}
+
+/// Locks the snapshot directory, creating it if needed. Fails with
+/// [`StorageError::Locked`] while another process holds the lock.
+pub fn lock(config: &Config) -> Result<StorageLock, StorageError> {
+    let dir = config.snapshot_dir();
+    std::fs::create_dir_all(dir)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))?;
+    match file.try_lock() {
+        Ok(()) => Ok(StorageLock { _file: file }),
+        Err(TryLockError::WouldBlock) => Err(StorageError::Locked(dir.to_path_buf())),
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct SnapshotHistory {
     pub snapshots: Vec<SystemSnapshot>,
 }
 
-impl Default for SnapshotHistory {
-    fn default() -> Self {
-        Self {
-            snapshots: Vec::new(),
-        }
-    }
+/// A compressed bundle holding every snapshot of one closed time window.
+#[derive(Debug, Clone)]
+struct ArchiveFile {
+    start: i64,
+    end: i64,
+    path: PathBuf,
 }
 
 pub async fn save_snapshot(
@@ -37,6 +81,7 @@ pub async fn save_snapshot(
 
     // Mevcut snapshot history'yi oku
     let mut history = read_history(&file).await.unwrap_or_default();
+    let previous = history.snapshots.last().map(|s| s.timestamp);
 
     // Yeni snapshot'ı ekle
     history.snapshots.push(snapshot.clone());
@@ -50,40 +95,410 @@ pub async fn save_snapshot(
 
     // JSON'a yaz
     let payload = serde_json::to_string_pretty(&history)?;
-    fs::write(&file, payload).await?;
+    write_atomic(&file, payload.as_bytes()).await?;
 
-    persist_time_series_file(snapshot, dir, limit).await?;
+    persist_time_series_file(snapshot, dir).await?;
+
+    // Archives only change when a window closes, so the directory is scanned
+    // once per window rather than on every save.
+    let window_ms = window_millis(config.archive_window());
+    let crossed = previous.is_none_or(|previous| {
+        window_start(previous, window_ms) != window_start(snapshot.timestamp, window_ms)
+    });
+    if crossed {
+        compact_closed_windows(dir, config.archive_window(), snapshot.timestamp).await?;
+        if let Some(budget) = config.storage_budget_bytes() {
+            enforce_disk_budget(dir, budget).await?;
+        }
+    }
 
     Ok(file)
 }
 
+/// Loads every stored snapshot with `from <= timestamp <= to`, reading both the
+/// per-snapshot files of the open window and the compressed archives.
+/// Results are sorted by ascending timestamp.
+pub async fn load_range(
+    config: &Config,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Vec<SystemSnapshot>, StorageError> {
+    let dir = config.snapshot_dir();
+    let from = from.unwrap_or(i64::MIN);
+    let to = to.unwrap_or(i64::MAX);
+    let mut snapshots = Vec::new();
+
+    // Per-snapshot files first: compaction writes the archive before removing
+    // them, so a file that disappears meanwhile is found in the archives, which
+    // are listed afterwards.
+    for (ts, path) in list_time_series(dir).await? {
+        if ts < from || ts > to {
+            continue;
+        }
+        if let Some(snapshot) = read_listed_snapshot(&path).await {
+            snapshots.push(snapshot);
+        }
+    }
+
+    for archive in list_archives(dir).await? {
+        if archive.end <= from || archive.start > to {
+            continue;
+        }
+        let path = archive.path.clone();
+        let entries = tokio::task::spawn_blocking(move || read_archive(&path)).await??;
+        snapshots.extend(
+            entries
+                .into_iter()
+                .filter(|s| s.timestamp >= from && s.timestamp <= to),
+        );
+    }
+
+    snapshots.sort_by_key(|s| s.timestamp);
+    snapshots.dedup_by_key(|s| s.timestamp);
+    Ok(snapshots)
+}
+
+/// Loads at most `max` stored snapshots with `from <= timestamp <= to`, newest
+/// first. Archives are only opened until enough snapshots have been found.
+pub async fn load_page(
+    config: &Config,
+    from: Option<i64>,
+    to: Option<i64>,
+    max: usize,
+) -> Result<Vec<SystemSnapshot>, StorageError> {
+    let dir = config.snapshot_dir();
+    let from = from.unwrap_or(i64::MIN);
+    let to = to.unwrap_or(i64::MAX);
+    let mut page: Vec<SystemSnapshot> = Vec::new();
+
+    // Archives and not-yet-compacted files may briefly overlap; keep each timestamp once.
+    let push = |page: &mut Vec<SystemSnapshot>, snapshot: SystemSnapshot| {
+        if page
+            .last()
+            .is_none_or(|last| snapshot.timestamp < last.timestamp)
+        {
+            page.push(snapshot);
+        }
+    };
+
+    for (ts, path) in list_time_series(dir).await?.into_iter().rev() {
+        if page.len() >= max {
+            return Ok(page);
+        }
+        if ts < from || ts > to {
+            continue;
+        }
+        if let Some(snapshot) = read_listed_snapshot(&path).await {
+            push(&mut page, snapshot);
+        }
+    }
+
+    // Listed after the per-snapshot files, see `load_range`.
+    for archive in list_archives(dir).await?.into_iter().rev() {
+        if page.len() >= max || archive.end <= from {
+            break;
+        }
+        if archive.start > to {
+            continue;
+        }
+        let path = archive.path.clone();
+        let entries = tokio::task::spawn_blocking(move || read_archive(&path)).await??;
+        for snapshot in entries.into_iter().rev() {
+            if page.len() >= max {
+                break;
+            }
+            if snapshot.timestamp >= from && snapshot.timestamp <= to {
+                push(&mut page, snapshot);
+            }
+        }
+    }
+
+    Ok(page)
+}
+
 async fn read_history(file: &PathBuf) -> Result<SnapshotHistory, StorageError> {
     let content = fs::read_to_string(file).await?;
     let history: SnapshotHistory = serde_json::from_str(&content)?;
     Ok(history)
 }
 
+async fn read_snapshot_file(path: &Path) -> Result<SystemSnapshot, StorageError> {
+    let content = fs::read(path).await?;
+    Ok(serde_json::from_slice(&content)?)
+}
+
+/// Reads a file returned by [`list_time_series`]. A file compacted into an
+/// archive since it was listed is skipped silently.
+async fn read_listed_snapshot(path: &Path) -> Option<SystemSnapshot> {
+    match read_snapshot_file(path).await {
+        Ok(snapshot) => Some(snapshot),
+        Err(StorageError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => {
+            warn!("skipping unreadable snapshot {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
 async fn persist_time_series_file(
     snapshot: &SystemSnapshot,
-    base_dir: &std::path::Path,
-    limit: usize,
+    base_dir: &Path,
 ) -> Result<(), StorageError> {
-    let time_series_dir = base_dir.join("time_series");
+    let time_series_dir = base_dir.join(TIME_SERIES_DIR);
     fs::create_dir_all(&time_series_dir).await?;
 
-    let file_name = format!("system_snapshot_{}.json", snapshot.timestamp);
+    let file_name = format!("{TIME_SERIES_PREFIX}{}.json", snapshot.timestamp);
     let file_path = time_series_dir.join(file_name);
 
-    let snapshot_json = serde_json::to_string_pretty(snapshot)?;
-    fs::write(&file_path, snapshot_json).await?;
+    let snapshot_json = serde_json::to_vec(snapshot)?;
+    write_atomic(&file_path, &snapshot_json).await?;
+
+    Ok(())
+}
+
+/// Writes to a hidden temporary file next to `path` and renames it into place,
+/// so an interrupted write never leaves a truncated file behind. The leading
+/// dot keeps leftovers out of [`list_time_series`].
+async fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), StorageError> {
+    let tmp = temp_path(path);
+    fs::write(&tmp, contents).await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
 
-    if limit == 0 {
+/// A hidden name next to `path` that is unique to this process.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+/// Bundles the per-snapshot files of every window that closed before `now`
+/// into one gzip-compressed JSON-lines archive and removes the originals.
+async fn compact_closed_windows(
+    base_dir: &Path,
+    window: Duration,
+    now: i64,
+) -> Result<(), StorageError> {
+    let window_ms = window_millis(window);
+    let current_window = window_start(now, window_ms);
+
+    let mut closed: BTreeMap<i64, Vec<PathBuf>> = BTreeMap::new();
+    for (ts, path) in list_time_series(base_dir).await? {
+        let start = window_start(ts, window_ms);
+        if start < current_window {
+            closed.entry(start).or_default().push(path);
+        }
+    }
+
+    if closed.is_empty() {
         return Ok(());
     }
 
-    let mut entries = fs::read_dir(&time_series_dir).await?;
+    let archive_dir = base_dir.join(ARCHIVE_DIR);
+    fs::create_dir_all(&archive_dir).await?;
+    let existing = list_archives(base_dir).await?;
+
+    for (start, paths) in closed {
+        // Late files for an already archived window are merged into it.
+        let previous = existing.iter().find(|a| a.start == start).cloned();
+        let target = archive_dir.join(format!(
+            "{ARCHIVE_PREFIX}{start}_{}{ARCHIVE_SUFFIX}",
+            start + window_ms
+        ));
+
+        let sources = paths.clone();
+        let archive_target = target.clone();
+        tokio::task::spawn_blocking(move || {
+            write_archive(&archive_target, previous.as_ref(), &sources)
+        })
+        .await??;
+
+        for path in paths {
+            let _ = fs::remove_file(path).await;
+        }
+    }
+
+    Ok(())
+}
+
+fn window_millis(window: Duration) -> i64 {
+    (window.as_millis() as i64).max(1)
+}
+
+fn window_start(ts: i64, window_ms: i64) -> i64 {
+    ts - ts.rem_euclid(window_ms)
+}
+
+fn write_archive(
+    target: &Path,
+    previous: Option<&ArchiveFile>,
+    sources: &[PathBuf],
+) -> Result<(), StorageError> {
+    let mut snapshots = match previous {
+        Some(archive) => read_archive(&archive.path)?,
+        None => Vec::new(),
+    };
+
+    for path in sources {
+        let parsed = std::fs::read(path)
+            .map_err(StorageError::from)
+            .and_then(|content| Ok(serde_json::from_slice::<SystemSnapshot>(&content)?));
+        match parsed {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(err) => warn!("dropping unreadable snapshot {}: {}", path.display(), err),
+        }
+    }
+
+    snapshots.sort_by_key(|s| s.timestamp);
+    snapshots.dedup_by_key(|s| s.timestamp);
+
+    let tmp = temp_path(target);
+    {
+        let mut encoder =
+            GzEncoder::new(BufWriter::new(File::create(&tmp)?), Compression::default());
+        for snapshot in &snapshots {
+            serde_json::to_writer(&mut encoder, snapshot)?;
+            encoder.write_all(b"\n")?;
+        }
+        encoder.finish()?.flush()?;
+    }
+    std::fs::rename(&tmp, target)?;
+
+    if let Some(archive) = previous {
+        if archive.path != target {
+            let _ = std::fs::remove_file(&archive.path);
+        }
+    }
+
+    Ok(())
+}
+
+fn read_archive(path: &Path) -> Result<Vec<SystemSnapshot>, StorageError> {
+    let reader = BufReader::new(GzDecoder::new(File::open(path)?));
+    let mut snapshots = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        snapshots.push(serde_json::from_str(&line)?);
+    }
+    Ok(snapshots)
+}
+
+/// Adds snapshots, e.g. from another agent's export, to the stored history.
+/// Snapshots of closed windows are merged into the archives; where a
+/// timestamp is already stored, the existing snapshot is kept. Returns the
+/// number of snapshots added.
+///
+/// The caller must hold the [`lock`] on the snapshot directory. Stored
+/// timestamps are looked up one archive window at a time, so only the
+/// archives the imported snapshots fall into are read.
+pub async fn import_snapshots(
+    snapshots: &[SystemSnapshot],
+    config: &Config,
+    _lock: &StorageLock,
+) -> Result<usize, StorageError> {
+    let dir = config.snapshot_dir();
+    let window_ms = window_millis(config.archive_window());
+    let mut windows: BTreeMap<i64, Vec<&SystemSnapshot>> = BTreeMap::new();
+    for snapshot in snapshots {
+        windows
+            .entry(window_start(snapshot.timestamp, window_ms))
+            .or_default()
+            .push(snapshot);
+    }
+
+    let open: HashSet<i64> = list_time_series(dir)
+        .await?
+        .into_iter()
+        .map(|(ts, _)| ts)
+        .collect();
+    let archives = list_archives(dir).await?;
+    let mut added = 0;
+    for (start, batch) in windows {
+        let mut stored = HashSet::new();
+        for archive in &archives {
+            if archive.end <= start || archive.start >= start + window_ms {
+                continue;
+            }
+            let path = archive.path.clone();
+            let entries = tokio::task::spawn_blocking(move || read_archive(&path)).await??;
+            stored.extend(entries.into_iter().map(|snapshot| snapshot.timestamp));
+        }
+        for snapshot in batch {
+            if !open.contains(&snapshot.timestamp) && stored.insert(snapshot.timestamp) {
+                persist_time_series_file(snapshot, dir).await?;
+                added += 1;
+            }
+        }
+    }
+    let now = chrono::Utc::now().timestamp_millis();
+    compact_closed_windows(dir, config.archive_window(), now).await?;
+
+    if let Some(budget) = config.storage_budget_bytes() {
+        enforce_disk_budget(dir, budget).await?;
+    }
+    Ok(added)
+}
+
+/// Total size of everything stored in the snapshot directory, in bytes.
+pub async fn stored_bytes(config: &Config) -> Result<u64, StorageError> {
+    let dir = config.snapshot_dir();
+    let mut total = file_size(&dir.join("system_snapshot.json")).await;
+    for (_, path) in list_time_series(dir).await? {
+        total += file_size(&path).await;
+    }
+    for archive in list_archives(dir).await? {
+        total += file_size(&archive.path).await;
+    }
+    Ok(total)
+}
+
+/// Deletes the oldest archives until the snapshot directory fits into `budget`
+/// bytes. The open window and the rolling history file are never pruned.
+async fn enforce_disk_budget(base_dir: &Path, budget: u64) -> Result<(), StorageError> {
+    let mut total = 0u64;
+    for (_, path) in list_time_series(base_dir).await? {
+        total += file_size(&path).await;
+    }
+    total += file_size(&base_dir.join("system_snapshot.json")).await;
+
+    let mut archives = Vec::new();
+    for archive in list_archives(base_dir).await? {
+        let size = file_size(&archive.path).await;
+        total += size;
+        archives.push((archive, size));
+    }
+
+    for (archive, size) in archives {
+        if total <= budget {
+            break;
+        }
+        fs::remove_file(&archive.path).await?;
+        total = total.saturating_sub(size);
+    }
+
+    Ok(())
+}
+
+async fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).await.map(|m| m.len()).unwrap_or(0)
+}
+
+async fn list_time_series(base_dir: &Path) -> Result<Vec<(i64, PathBuf)>, StorageError> {
+    let time_series_dir = base_dir.join(TIME_SERIES_DIR);
     let mut files: Vec<(i64, PathBuf)> = Vec::new();
 
+    let mut entries = match fs::read_dir(&time_series_dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(err) => return Err(err.into()),
+    };
+
     while let Some(entry) = entries.next_entry().await? {
         let path = entry.path();
         if !path.is_file() {
@@ -94,7 +509,7 @@ async fn persist_time_series_file(
             continue;
         };
 
-        let Some(ts_str) = stem.strip_prefix("system_snapshot_") else {
+        let Some(ts_str) = stem.strip_prefix(TIME_SERIES_PREFIX) else {
             continue;
         };
 
@@ -104,16 +519,240 @@ async fn persist_time_series_file(
         }
     }
 
-    if files.len() <= limit {
-        return Ok(());
+    files.sort_by_key(|(ts, _)| *ts);
+    Ok(files)
+}
+
+async fn list_archives(base_dir: &Path) -> Result<Vec<ArchiveFile>, StorageError> {
+    let archive_dir = base_dir.join(ARCHIVE_DIR);
+    let mut archives = Vec::new();
+
+    let mut entries = match fs::read_dir(&archive_dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(archives),
+        Err(err) => return Err(err.into()),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
+            continue;
+        };
+
+        let Some(range) = name
+            .strip_prefix(ARCHIVE_PREFIX)
+            .and_then(|rest| rest.strip_suffix(ARCHIVE_SUFFIX))
+        else {
+            continue;
+        };
+
+        let Some((start, end)) = range.split_once('_') else {
+            continue;
+        };
+
+        if let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>()) {
+            archives.push(ArchiveFile { start, end, path });
+        }
     }
 
-    files.sort_by_key(|(ts, _)| *ts);
-    let prune_count = files.len().saturating_sub(limit);
+    archives.sort_by_key(|a| a.start);
+    Ok(archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    const WINDOW_MS: i64 = 60_000;
+    // Start of an archive window, well in the past.
+    const BASE: i64 = 1_700_000_040_000;
 
-    for (_, path) in files.into_iter().take(prune_count) {
-        let _ = fs::remove_file(path).await;
+    /// A snapshot directory in a fresh temporary directory and a configuration
+    /// using it, with one-minute archive windows.
+    struct Store {
+        dir: PathBuf,
+        config: Config,
     }
 
-    Ok(())
+    impl Store {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "agent-storage-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            let file = dir.join("agent.toml");
+            std::fs::write(
+                &file,
+                format!(
+                    "[auth]\napi_key = \"test\"\n\n\
+                     [storage]\nsnapshot_dir = \"{}\"\narchive_window_secs = 60\nmax_mb = 0\n\n\
+                     [audit]\npath = \"\"\n",
+                    dir.join("snapshots").display()
+                ),
+            )
+            .unwrap();
+            let config = Config::load_isolated(&file).unwrap();
+            Self { dir, config }
+        }
+
+        fn snapshots(&self) -> &Path {
+            self.config.snapshot_dir()
+        }
+
+        async fn save(&self, timestamps: &[i64]) {
+            for &timestamp in timestamps {
+                save_snapshot(&snapshot(timestamp), &self.config)
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    impl Drop for Store {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn snapshot(timestamp: i64) -> SystemSnapshot {
+        SystemSnapshot {
+            timestamp,
+            ..SystemSnapshot::default()
+        }
+    }
+
+    fn timestamps(snapshots: &[SystemSnapshot]) -> Vec<i64> {
+        snapshots.iter().map(|s| s.timestamp).collect()
+    }
+
+    async fn archive_starts(store: &Store) -> Vec<i64> {
+        list_archives(store.snapshots())
+            .await
+            .unwrap()
+            .iter()
+            .map(|archive| archive.start)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn compacts_closed_windows_and_reads_them_back() {
+        let store = Store::new();
+        let next = BASE + WINDOW_MS;
+        store
+            .save(&[BASE + 1_000, BASE + 2_000, BASE + 3_000, next + 1_000])
+            .await;
+
+        let archive = store
+            .snapshots()
+            .join(ARCHIVE_DIR)
+            .join(format!("{ARCHIVE_PREFIX}{BASE}_{next}{ARCHIVE_SUFFIX}"));
+        assert_eq!(
+            timestamps(&read_archive(&archive).unwrap()),
+            [BASE + 1_000, BASE + 2_000, BASE + 3_000]
+        );
+        // Only the open window is left as individual files.
+        let open: Vec<i64> = list_time_series(store.snapshots())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(ts, _)| ts)
+            .collect();
+        assert_eq!(open, [next + 1_000]);
+
+        let all = load_range(&store.config, None, None).await.unwrap();
+        assert_eq!(
+            timestamps(&all),
+            [BASE + 1_000, BASE + 2_000, BASE + 3_000, next + 1_000]
+        );
+        let range = load_range(&store.config, Some(BASE + 2_000), Some(next))
+            .await
+            .unwrap();
+        assert_eq!(timestamps(&range), [BASE + 2_000, BASE + 3_000]);
+
+        // Pages run newest first, across the open window into the archive.
+        let page = load_page(&store.config, None, None, 3).await.unwrap();
+        assert_eq!(
+            timestamps(&page),
+            [next + 1_000, BASE + 3_000, BASE + 2_000]
+        );
+        let page = load_page(&store.config, None, Some(BASE + 2_999), 3)
+            .await
+            .unwrap();
+        assert_eq!(timestamps(&page), [BASE + 2_000, BASE + 1_000]);
+    }
+
+    #[tokio::test]
+    async fn disk_budget_prunes_oldest_archives_first() {
+        let store = Store::new();
+        let windows: Vec<i64> = (0..4).map(|n| BASE + n * WINDOW_MS).collect();
+        for &start in &windows {
+            store.save(&[start + 1_000, start + 2_000]).await;
+        }
+        assert_eq!(archive_starts(&store).await, windows[..3]);
+
+        // Leave room for everything but the oldest archive.
+        let oldest = list_archives(store.snapshots()).await.unwrap()[0].clone();
+        let total = stored_bytes(&store.config).await.unwrap();
+        let budget = total - file_size(&oldest.path).await;
+        enforce_disk_budget(store.snapshots(), budget)
+            .await
+            .unwrap();
+        assert_eq!(archive_starts(&store).await, windows[1..3]);
+
+        // Nothing archived is kept once the budget is tiny; the open window is.
+        enforce_disk_budget(store.snapshots(), 1).await.unwrap();
+        assert!(archive_starts(&store).await.is_empty());
+        let left = load_range(&store.config, None, None).await.unwrap();
+        assert_eq!(timestamps(&left), [windows[3] + 1_000, windows[3] + 2_000]);
+    }
+
+    #[tokio::test]
+    async fn imports_into_archives_without_duplicates() {
+        let store = Store::new();
+        let next = BASE + WINDOW_MS;
+        store.save(&[BASE + 1_000, next + 1_000]).await;
+        let lock = lock(&store.config).unwrap();
+
+        let incoming: Vec<SystemSnapshot> = [BASE + 1_000, BASE + 500, next + 1_000, next + 2_000]
+            .into_iter()
+            .map(snapshot)
+            .collect();
+        assert_eq!(
+            import_snapshots(&incoming, &store.config, &lock)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            import_snapshots(&incoming, &store.config, &lock)
+                .await
+                .unwrap(),
+            0
+        );
+
+        let all = load_range(&store.config, None, None).await.unwrap();
+        assert_eq!(
+            timestamps(&all),
+            [BASE + 500, BASE + 1_000, next + 1_000, next + 2_000]
+        );
+        // Both windows lie in the past, so everything ends up archived.
+        assert_eq!(archive_starts(&store).await, [BASE, next]);
+    }
+
+    #[test]
+    fn lock_is_refused_to_a_second_holder() {
+        let store = Store::new();
+        let held = lock(&store.config).unwrap();
+        assert!(matches!(
+            lock(&store.config),
+            Err(StorageError::Locked(dir)) if dir == store.snapshots()
+        ));
+        drop(held);
+        assert!(lock(&store.config).is_ok());
+    }
 }
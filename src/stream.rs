@@ -0,0 +1,108 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+
+use crate::{
+    api::{self, ApiError, ApiQuery, Caller},
+    collector::SystemSnapshot,
+    keys::Scope,
+    series::MetricPath,
+    state::SharedState,
+};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Comma separated metric paths; without it every event carries the full snapshot.
+    pub metrics: Option<String>,
+}
+
+/// Server-sent events with one `snapshot` event per collection.
+#[utoipa::path(
+    get,
+    path = "/api/v1/stream",
+    tag = "streaming",
+    params(
+        ("metrics" = Option<String>, Query, description = "Comma separated metric paths"),
+    ),
+    responses(
+        (status = 200, description = "Event stream", content_type = "text/event-stream", body = String),
+        (status = 400, body = api::ErrorResponse),
+        (status = 401, body = api::ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn stream(
+    State(state): State<SharedState>,
+    caller: Caller,
+    ApiQuery(query): ApiQuery<StreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let principal = caller.authorise(Scope::ReadMetrics)?;
+
+    let metrics = parse_metric_list(query.metrics.as_deref().unwrap_or_default())?;
+    let events = BroadcastStream::new(state.subscribe()).map(move |message| {
+        let event = match message {
+            Ok(mut snapshot) => {
+                api::redact_processes(&principal, [&mut snapshot]);
+                snapshot_event(&snapshot, &metrics)
+            }
+            // The client was too slow; tell it how many snapshots it missed.
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                Event::default().event("lagged").data(skipped.to_string())
+            }
+        };
+        Some(Ok(event))
+    });
+    // End the response on shutdown so the server can finish draining.
+    let shutdown = tokio_stream::once(())
+        .then(move |()| state.shutdown_requested())
+        .map(|()| None);
+    let events = events.merge(shutdown).map_while(|event| event);
+
+    Ok(Sse::new(events).keep_alive(
+        KeepAlive::new()
+            .interval(HEARTBEAT_INTERVAL)
+            .text("heartbeat"),
+    ))
+}
+
+pub fn parse_metric_list(raw: &str) -> Result<Vec<MetricPath>, ApiError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(|path| path.parse().map_err(ApiError::from))
+        .collect()
+}
+
+/// Reduces a snapshot to `{timestamp, metrics: {path: value}}`, or returns the
+/// whole snapshot when no metrics were requested.
+pub fn project(snapshot: &SystemSnapshot, metrics: &[MetricPath]) -> Value {
+    let full = serde_json::to_value(snapshot).unwrap_or(Value::Null);
+    if metrics.is_empty() {
+        return full;
+    }
+
+    let values: Map<String, Value> = metrics
+        .iter()
+        .map(|path| (path.to_string(), json!(path.resolve_value(&full))))
+        .collect();
+
+    json!({ "timestamp": snapshot.timestamp, "metrics": values })
+}
+
+fn snapshot_event(snapshot: &SystemSnapshot, metrics: &[MetricPath]) -> Event {
+    Event::default()
+        .event("snapshot")
+        .id(snapshot.timestamp.to_string())
+        .data(project(snapshot, metrics).to_string())
+}
@@ -0,0 +1,463 @@
+use std::{
+    fs::{self, File},
+    future::Future,
+    io::{self, BufReader},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use axum::{middleware::AddExtension, Extension};
+use axum_server::{
+    accept::{Accept, DefaultAcceptor},
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
+use rustls::{
+    crypto::ring,
+    server::{ServerConfig, WebPkiClientVerifier},
+    RootCertStore,
+};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+use tracing::{info, warn};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::config::TlsConfig;
+
+// How often the certificate files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("could not read {0}: {1}")]
+    Io(String, io::Error),
+    #[error("no certificate found in {0}")]
+    NoCertificate(String),
+    #[error("no private key found in {0}")]
+    NoPrivateKey(String),
+    #[error("invalid TLS configuration: {0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("invalid client CA bundle: {0}")]
+    ClientCa(String),
+}
+
+/// Builds the rustls server configuration from the PEM files in `tls`.
+pub fn server_config(tls: &TlsConfig) -> Result<Arc<ServerConfig>, TlsError> {
+    let provider = Arc::new(ring::default_provider());
+    let certs = read_certs(&tls.cert)?;
+    let key_path = tls.key.display().to_string();
+    let key = rustls_pemfile::private_key(&mut open(&tls.key)?)
+        .map_err(|err| TlsError::Io(key_path.clone(), err))?
+        .ok_or(TlsError::NoPrivateKey(key_path))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &tls.client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(ca)? {
+                roots
+                    .add(cert)
+                    .map_err(|err| TlsError::ClientCa(err.to_string()))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            // Unless required, clients without a certificate may still use API keys.
+            let verifier = if tls.client_cert_required {
+                verifier
+            } else {
+                verifier.allow_unauthenticated()
+            };
+            builder.with_client_cert_verifier(
+                verifier
+                    .build()
+                    .map_err(|err| TlsError::ClientCa(err.to_string()))?,
+            )
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder.with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Re-reads the certificate, key and CA bundle whenever one of them changes,
+/// so renewed certificates are picked up without a restart.
+pub fn spawn_reload(tls: TlsConfig, rustls: RustlsConfig) {
+    tokio::spawn(async move {
+        let mut last = modified(&tls);
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+            reload_if_changed(&tls, &rustls, &mut last);
+        }
+    });
+}
+
+/// Reloads `rustls` when a file changed since `last` was taken. Returns
+/// whether the new certificate is now in use.
+fn reload_if_changed(
+    tls: &TlsConfig,
+    rustls: &RustlsConfig,
+    last: &mut Vec<Option<SystemTime>>,
+) -> bool {
+    let current = modified(tls);
+    if current == *last {
+        return false;
+    }
+    *last = current;
+
+    // A half-written renewal keeps the previous certificate active.
+    match server_config(tls) {
+        Ok(config) => {
+            rustls.reload_from_config(config);
+            info!("🔐 Reloaded TLS certificate from {}", tls.cert.display());
+            true
+        }
+        Err(err) => {
+            warn!("keeping previous TLS certificate: {}", err);
+            false
+        }
+    }
+}
+
+fn modified(tls: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [Some(&tls.cert), Some(&tls.key), tls.client_ca.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| TlsError::Io(path.display().to_string(), err))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>, TlsError> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| TlsError::Io(path.display().to_string(), err))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(path.display().to_string()));
+    }
+    Ok(certs)
+}
+
+/// Subject of a verified client certificate, attached to every request on
+/// that connection.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    pub subject: String,
+}
+
+/// TLS acceptor that records the verified client certificate of each
+/// connection as a request extension.
+#[derive(Debug, Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor<DefaultAcceptor>,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(config: RustlsConfig) -> Self {
+        Self {
+            inner: RustlsAcceptor::new(config),
+        }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, Option<ClientCertificate>>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .and_then(|cert| subject(cert));
+            Ok((stream, Extension(certificate).layer(service)))
+        })
+    }
+}
+
+/// Common name of the certificate, or its full subject when it has none.
+fn subject(cert: &[u8]) -> Option<ClientCertificate> {
+    let (_, parsed) = X509Certificate::from_der(cert).ok()?;
+    let subject = parsed
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| parsed.subject().to_string());
+    Some(ClientCertificate { subject })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{SocketAddr, TcpListener},
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, KeyPair};
+    use rustls::{
+        pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+        ClientConfig,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+    use crate::{collector::SystemSnapshot, config::Config, keys::KeyStore, state::AppState};
+
+    const API_KEY: &str = "tls-test-key";
+
+    /// A certificate with its key, signed by `issuer` or self-signed.
+    struct Issued {
+        cert: Certificate,
+        key: KeyPair,
+    }
+
+    impl Issued {
+        fn ca(name: &str) -> Self {
+            let mut params = CertificateParams::default();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params.distinguished_name.push(DnType::CommonName, name);
+            let key = KeyPair::generate().unwrap();
+            let cert = params.self_signed(&key).unwrap();
+            Self { cert, key }
+        }
+
+        fn leaf(&self, name: &str) -> Self {
+            let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+            params.distinguished_name.push(DnType::CommonName, name);
+            let key = KeyPair::generate().unwrap();
+            let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+            Self { cert, key }
+        }
+    }
+
+    /// Certificate files in a fresh temporary directory.
+    struct Files {
+        dir: PathBuf,
+    }
+
+    impl Files {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "agent-tls-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            Self { dir }
+        }
+
+        fn write_server(&self, server: &Issued) {
+            fs::write(self.dir.join("server.pem"), server.cert.pem()).unwrap();
+            fs::write(self.dir.join("server.key"), server.key.serialize_pem()).unwrap();
+        }
+
+        /// Moves the server files' modification time to `time`, so a rewrite is
+        /// noticed regardless of the filesystem's timestamp granularity.
+        fn touch_server(&self, time: SystemTime) {
+            for name in ["server.pem", "server.key"] {
+                fs::File::options()
+                    .write(true)
+                    .open(self.dir.join(name))
+                    .and_then(|file| file.set_modified(time))
+                    .unwrap();
+            }
+        }
+
+        fn write_client_ca(&self, ca: &Issued) {
+            fs::write(self.dir.join("client-ca.pem"), ca.cert.pem()).unwrap();
+        }
+
+        /// Loads a configuration serving these files, requiring API keys.
+        fn config(&self, client_ca: bool, client_cert_required: bool) -> Config {
+            let path = |name: &str| self.dir.join(name).display().to_string();
+            let mut toml = format!(
+                "[auth]\napi_key = \"{API_KEY}\"\n\n\
+                 [storage]\nsnapshot_dir = \"{}\"\n\n\
+                 [audit]\npath = \"\"\n\n\
+                 [tls]\ncert = \"{}\"\nkey = \"{}\"\nclient_cert_required = {client_cert_required}\n",
+                path("snapshots"),
+                path("server.pem"),
+                path("server.key"),
+            );
+            if client_ca {
+                toml.push_str(&format!("client_ca = \"{}\"\n", path("client-ca.pem")));
+            }
+            let file = self.dir.join("agent.toml");
+            fs::write(&file, toml).unwrap();
+            Config::load_isolated(&file).unwrap()
+        }
+    }
+
+    impl Drop for Files {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Serves the full application over TLS on a random local port.
+    fn serve(config: &Config) -> (SocketAddr, RustlsConfig) {
+        let tls = config.tls().unwrap();
+        let rustls = RustlsConfig::from_config(server_config(tls).unwrap());
+        let state = Arc::new(AppState::new(
+            config.clone(),
+            KeyStore::open(None).unwrap(),
+            SystemSnapshot::default(),
+        ));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::app(state).into_make_service_with_connect_info::<SocketAddr>();
+        let server = axum_server::from_tcp(listener)
+            .acceptor(ClientCertAcceptor::new(rustls.clone()))
+            .serve(app);
+        tokio::spawn(server);
+        (addr, rustls)
+    }
+
+    /// Sends `GET /api/v1/system` and returns the HTTP status. Fails when the
+    /// handshake or the connection is rejected.
+    async fn get(
+        addr: SocketAddr,
+        trusted: &Issued,
+        client: Option<&Issued>,
+        api_key: Option<&str>,
+    ) -> io::Result<u16> {
+        let provider = Arc::new(ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots.add(trusted.cert.der().clone()).unwrap();
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = match client {
+            Some(client) => builder
+                .with_client_auth_cert(
+                    vec![client.cert.der().clone()],
+                    PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(client.key.serialize_der())),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        let stream = TcpStream::connect(addr).await?;
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await?;
+
+        let mut request =
+            "GET /api/v1/system HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n".to_string();
+        if let Some(key) = api_key {
+            request.push_str(&format!("Authorization: Bearer {key}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        let response = String::from_utf8_lossy(&response);
+        response
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no HTTP response"))
+    }
+
+    #[tokio::test]
+    async fn serves_https_with_api_key() {
+        let ca = Issued::ca("server CA");
+        let files = Files::new();
+        files.write_server(&ca.leaf("localhost"));
+        let (addr, _) = serve(&files.config(false, false));
+
+        assert_eq!(get(addr, &ca, None, Some(API_KEY)).await.unwrap(), 200);
+        assert_eq!(get(addr, &ca, None, None).await.unwrap(), 401);
+        // A client that does not trust the server's CA aborts the handshake.
+        assert!(get(addr, &Issued::ca("other CA"), None, Some(API_KEY))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn reloads_rewritten_certificate() {
+        let old_ca = Issued::ca("old CA");
+        let new_ca = Issued::ca("new CA");
+        let files = Files::new();
+        files.write_server(&old_ca.leaf("localhost"));
+        let config = files.config(false, false);
+        let tls = config.tls().unwrap();
+        let (addr, rustls) = serve(&config);
+        let mut last = modified(tls);
+
+        assert!(!reload_if_changed(tls, &rustls, &mut last));
+        assert_eq!(get(addr, &old_ca, None, Some(API_KEY)).await.unwrap(), 200);
+
+        files.write_server(&new_ca.leaf("localhost"));
+        files.touch_server(SystemTime::now() + Duration::from_secs(60));
+        assert!(reload_if_changed(tls, &rustls, &mut last));
+        assert_eq!(get(addr, &new_ca, None, Some(API_KEY)).await.unwrap(), 200);
+        assert!(get(addr, &old_ca, None, Some(API_KEY)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn client_certificate_replaces_api_key() {
+        let ca = Issued::ca("server CA");
+        let client_ca = Issued::ca("client CA");
+        let files = Files::new();
+        files.write_server(&ca.leaf("localhost"));
+        files.write_client_ca(&client_ca);
+        let (addr, _) = serve(&files.config(true, false));
+
+        let client = client_ca.leaf("reporter");
+        assert_eq!(get(addr, &ca, Some(&client), None).await.unwrap(), 200);
+        // Without a certificate the API key is still accepted.
+        assert_eq!(get(addr, &ca, None, Some(API_KEY)).await.unwrap(), 200);
+        assert_eq!(get(addr, &ca, None, None).await.unwrap(), 401);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_or_untrusted_client_certificate() {
+        let ca = Issued::ca("server CA");
+        let client_ca = Issued::ca("client CA");
+        let files = Files::new();
+        files.write_server(&ca.leaf("localhost"));
+        files.write_client_ca(&client_ca);
+
+        let (optional, _) = serve(&files.config(true, false));
+        let untrusted = Issued::ca("rogue CA").leaf("reporter");
+        assert!(get(optional, &ca, Some(&untrusted), None).await.is_err());
+
+        let (required, _) = serve(&files.config(true, true));
+        assert!(get(required, &ca, None, Some(API_KEY)).await.is_err());
+        assert!(get(required, &ca, Some(&untrusted), None).await.is_err());
+        let client = client_ca.leaf("reporter");
+        assert_eq!(get(required, &ca, Some(&client), None).await.unwrap(), 200);
+    }
+}
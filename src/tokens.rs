@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use axum::{
+    body::Bytes,
+    extract::{Query, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use utoipa::ToSchema;
+
+use crate::{
+    api::{self, ApiError, ApiQuery, Caller},
+    auth,
+    config::TokenConfig,
+    keys::{Principal, Scope},
+    state::SharedState,
+};
+
+// Only HS256 is issued and accepted, so the header is a fixed string.
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// Claims of a signed access token. An empty route list grants every route
+/// the scopes allow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub scopes: Vec<Scope>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<String>,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+impl Claims {
+    /// The caller as seen by the scope checks and the audit log.
+    pub fn principal(&self) -> Principal {
+        Principal::new(format!("token:{}", self.sub), self.scopes.clone())
+    }
+
+    /// Whether `path` is one of the token's routes or below one of them.
+    /// Versioned and unversioned API paths are treated alike.
+    pub fn permits(&self, path: &str) -> bool {
+        let path = api::unversioned(path);
+        self.routes.is_empty()
+            || self.routes.iter().any(|route| {
+                let route = api::unversioned(route);
+                let route = route.trim_end_matches('/');
+                path == route
+                    || path
+                        .strip_prefix(route)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+    }
+}
+
+/// Issues and verifies JWT-style tokens signed with HMAC-SHA256.
+pub struct TokenSigner {
+    key: Vec<u8>,
+}
+
+impl TokenSigner {
+    pub fn new(config: &TokenConfig) -> Self {
+        let key = config.signing_key.clone().unwrap_or_else(|| {
+            let mut key = vec![0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        });
+        Self { key }
+    }
+
+    pub fn issue(&self, claims: &Claims) -> String {
+        let payload = serde_json::to_vec(claims).expect("claims serialise to JSON");
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(HEADER),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&message).finalize().into_bytes());
+        format!("{message}.{signature}")
+    }
+
+    /// Returns the claims of a correctly signed, unexpired token.
+    pub fn verify(&self, token: &str) -> Option<Claims> {
+        let (message, signature) = token.rsplit_once('.')?;
+        let (header, payload) = message.split_once('.')?;
+        if URL_SAFE_NO_PAD.decode(header).ok()? != HEADER.as_bytes() {
+            return None;
+        }
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.mac(message).verify_slice(&signature).ok()?;
+
+        let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        (claims.exp > Utc::now().timestamp()).then_some(claims)
+    }
+
+    fn mac(&self, message: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(message.as_bytes());
+        mac
+    }
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct TokenRequest {
+    /// Defaults to every scope of the caller.
+    pub scopes: Option<Vec<Scope>>,
+    /// Path prefixes the token is limited to, e.g. `/api/stream`.
+    pub routes: Vec<String>,
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenResponse {
+    pub token: String,
+    pub token_type: &'static str,
+    pub expires_at: DateTime<Utc>,
+    pub scopes: Vec<Scope>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<String>,
+}
+
+/// Exchanges an API key (or dashboard session) for a short-lived token with
+/// the same or fewer scopes. Tokens cannot be used to issue further tokens.
+#[utoipa::path(
+    post,
+    path = "/api/v1/tokens",
+    tag = "auth",
+    request_body(content = Option<TokenRequest>, description = "All fields are optional"),
+    responses(
+        (status = 200, body = TokenResponse),
+        (status = 400, body = api::ErrorResponse),
+        (status = 401, body = api::ErrorResponse),
+        (status = 403, description = "Scope exceeds the caller's", body = api::ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn issue(
+    State(state): State<SharedState>,
+    caller: Caller,
+    headers: HeaderMap,
+    ApiQuery(query): ApiQuery<HashMap<String, String>>,
+    body: Bytes,
+) -> Result<Json<TokenResponse>, ApiError> {
+    let principal = caller.principal()?;
+    if auth::presented_credentials(&headers, &query)
+        .any(|(credential, _)| state.tokens().verify(credential).is_some())
+    {
+        return Err(ApiError::BadRequest(
+            "a token cannot be exchanged for another token".to_string(),
+        ));
+    }
+
+    let request: TokenRequest = if body.is_empty() {
+        TokenRequest::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|err| ApiError::BadRequest(format!("invalid token request: {err}")))?
+    };
+    let scopes = request.scopes.unwrap_or_else(|| principal.scopes.clone());
+    if scopes.is_empty() {
+        return Err(ApiError::BadRequest(
+            "at least one scope is required".to_string(),
+        ));
+    }
+    if let Some(scope) = scopes.iter().find(|scope| !principal.allows(**scope)) {
+        return Err(ApiError::Forbidden {
+            key: principal.name,
+            scope: *scope,
+        });
+    }
+    if let Some(route) = request.routes.iter().find(|route| !route.starts_with('/')) {
+        return Err(ApiError::BadRequest(format!(
+            "route `{route}` must start with /"
+        )));
+    }
+
+    let config = state.config();
+    let limits = config.tokens();
+    let ttl = match request.ttl_secs {
+        None => limits.default_ttl.as_secs(),
+        Some(secs) if secs > 0 && secs <= limits.max_ttl.as_secs() => secs,
+        Some(secs) => {
+            return Err(ApiError::BadRequest(format!(
+                "ttl_secs must be between 1 and {}, got {secs}",
+                limits.max_ttl.as_secs()
+            )))
+        }
+    };
+
+    let now = Utc::now();
+    let expires_at = now + chrono::Duration::seconds(ttl as i64);
+    let claims = Claims {
+        sub: principal.name,
+        scopes,
+        routes: request.routes,
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
+    };
+
+    Ok(Json(TokenResponse {
+        token: state.tokens().issue(&claims),
+        token_type: "Bearer",
+        expires_at,
+        scopes: claims.scopes,
+        routes: claims.routes,
+    }))
+}
+
+/// Middleware rejecting requests outside the routes a presented token is
+/// limited to. Scopes are checked by the handlers as for API keys.
+pub async fn restrict(State(state): State<SharedState>, request: Request, next: Next) -> Response {
+    let query = Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .map(|Query(query)| query)
+        .unwrap_or_default();
+    let path = request.uri().path();
+    let denied = auth::presented_credentials(request.headers(), &query)
+        .filter_map(|(credential, _)| state.tokens().verify(credential))
+        .any(|claims| !claims.permits(path));
+    if denied {
+        return ApiError::RouteNotAllowed(path.to_string()).into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn signer(key: &[u8]) -> TokenSigner {
+        TokenSigner::new(&TokenConfig {
+            signing_key: Some(key.to_vec()),
+            default_ttl: Duration::from_secs(300),
+            max_ttl: Duration::from_secs(3600),
+            keys_in_query: false,
+        })
+    }
+
+    fn claims(expires_in: i64) -> Claims {
+        let now = Utc::now().timestamp();
+        Claims {
+            sub: "ops".to_string(),
+            scopes: vec![Scope::ReadMetrics],
+            routes: Vec::new(),
+            iat: now,
+            exp: now + expires_in,
+        }
+    }
+
+    fn resign(signer: &TokenSigner, header: &str, payload: &str) -> String {
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let signature = URL_SAFE_NO_PAD.encode(signer.mac(&message).finalize().into_bytes());
+        format!("{message}.{signature}")
+    }
+
+    #[test]
+    fn verifies_issued_tokens() {
+        let signer = signer(&[7; 32]);
+        let token = signer.issue(&claims(60));
+
+        let verified = signer.verify(&token).unwrap();
+        assert_eq!(verified.sub, "ops");
+        assert_eq!(verified.scopes, vec![Scope::ReadMetrics]);
+        assert_eq!(verified.principal().name, "token:ops");
+    }
+
+    #[test]
+    fn rejects_expired_tokens() {
+        let signer = signer(&[7; 32]);
+        assert!(signer.verify(&signer.issue(&claims(-1))).is_none());
+        assert!(signer.verify(&signer.issue(&claims(0))).is_none());
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let signer = signer(&[7; 32]);
+        let token = signer.issue(&claims(60));
+
+        assert!(self::signer(&[8; 32]).verify(&token).is_none());
+
+        let (message, signature) = token.rsplit_once('.').unwrap();
+        let mut tampered = URL_SAFE_NO_PAD.decode(signature).unwrap();
+        tampered[0] ^= 1;
+        let tampered = format!("{message}.{}", URL_SAFE_NO_PAD.encode(tampered));
+        assert!(signer.verify(&tampered).is_none());
+        assert!(signer.verify(&format!("{message}.")).is_none());
+        assert!(signer.verify(message).is_none());
+
+        // Raising the scopes invalidates the signature.
+        let (header, _) = message.split_once('.').unwrap();
+        let mut forged = claims(60);
+        forged.scopes = vec![Scope::Admin];
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        assert!(signer
+            .verify(&format!("{header}.{payload}.{signature}"))
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_other_headers() {
+        let signer = signer(&[7; 32]);
+        let payload = serde_json::to_string(&claims(60)).unwrap();
+        assert!(signer.verify(&resign(&signer, HEADER, &payload)).is_some());
+
+        for header in [
+            r#"{"alg":"none","typ":"JWT"}"#,
+            r#"{"alg":"HS512","typ":"JWT"}"#,
+            r#"{"typ":"JWT","alg":"HS256"}"#,
+            "",
+        ] {
+            assert!(
+                signer.verify(&resign(&signer, header, &payload)).is_none(),
+                "{header} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn routes_limit_paths_below_them() {
+        let mut claims = claims(60);
+        assert!(claims.permits("/api/history"));
+
+        claims.routes = vec!["/api/stream/".to_string()];
+        assert!(claims.permits("/api/stream"));
+        assert!(claims.permits("/api/v1/stream/metrics"));
+        assert!(!claims.permits("/api/streams"));
+        assert!(!claims.permits("/api/history"));
+    }
+}
@@ -0,0 +1,319 @@
+use std::{net::IpAddr, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    Extension,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{
+    access::ClientIp,
+    api::{self, ApiError, Caller},
+    collector::SystemSnapshot,
+    health::AlertEvent,
+    keys::{Principal, Scope},
+    series::MetricPath,
+    state::SharedState,
+    stream,
+};
+
+// Extra time granted to an on-demand collection on top of the regular interval.
+const COLLECT_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Topic {
+    #[default]
+    Snapshots,
+    Alerts,
+}
+
+impl Topic {
+    fn as_str(self) -> &'static str {
+        match self {
+            Topic::Snapshots => "snapshots",
+            Topic::Alerts => "alerts",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        id: Option<Value>,
+        #[serde(default)]
+        topic: Topic,
+        #[serde(default)]
+        metrics: Vec<String>,
+    },
+    Unsubscribe {
+        id: Option<Value>,
+        /// Without a topic every subscription ends.
+        topic: Option<Topic>,
+    },
+    Latest {
+        id: Option<Value>,
+    },
+    History {
+        id: Option<Value>,
+        from: Option<i64>,
+        to: Option<i64>,
+        cursor: Option<i64>,
+        limit: Option<usize>,
+    },
+    Collect {
+        id: Option<Value>,
+    },
+}
+
+struct Subscription {
+    receiver: broadcast::Receiver<SystemSnapshot>,
+    metrics: Vec<MetricPath>,
+}
+
+/// The topics a connection is subscribed to.
+#[derive(Default)]
+struct Subscriptions {
+    snapshots: Option<Subscription>,
+    alerts: Option<broadcast::Receiver<AlertEvent>>,
+}
+
+/// WebSocket API; see the README for the message protocol.
+#[utoipa::path(
+    get,
+    path = "/api/v1/ws",
+    tag = "streaming",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 401, body = api::ErrorResponse),
+    ),
+    security(("bearer" = []), ("token" = [])),
+)]
+pub async fn upgrade(
+    State(state): State<SharedState>,
+    caller: Caller,
+    client: Option<Extension<ClientIp>>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // Authenticate before upgrading so rejected clients get a regular HTTP error.
+    let principal = caller.authorise(Scope::ReadMetrics)?;
+    let client_ip = client.map(|Extension(ClientIp(ip))| ip);
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state, principal, client_ip)))
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    state: SharedState,
+    principal: Principal,
+    client_ip: Option<IpAddr>,
+) {
+    let mut subscriptions = Subscriptions::default();
+    // Replies produced by background work (e.g. `collect`) are routed through here.
+    let (outbox, mut replies) = mpsc::channel::<Value>(8);
+
+    loop {
+        let outgoing = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    handle_message(&state, &principal, client_ip, &text, &mut subscriptions, &outbox)
+                        .await
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => None,
+            },
+            event = next_snapshot(&principal, &mut subscriptions.snapshots) => event,
+            event = next_alert(&mut subscriptions.alerts) => event,
+            Some(reply) = replies.recv() => Some(reply),
+            _ = state.shutdown_requested() => {
+                let _ = socket.send(Message::Close(Some(CloseFrame {
+                    code: close_code::AWAY,
+                    reason: "server shutting down".into(),
+                }))).await;
+                break;
+            }
+        };
+
+        if let Some(payload) = outgoing {
+            if socket
+                .send(Message::Text(payload.to_string()))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    }
+}
+
+async fn next_snapshot(
+    principal: &Principal,
+    subscription: &mut Option<Subscription>,
+) -> Option<Value> {
+    let Some(active) = subscription else {
+        return std::future::pending().await;
+    };
+
+    match active.receiver.recv().await {
+        Ok(mut snapshot) => {
+            api::redact_processes(principal, [&mut snapshot]);
+            Some(json!({
+                "type": "snapshot",
+                "data": stream::project(&snapshot, &active.metrics),
+            }))
+        }
+        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+            Some(json!({ "type": "lagged", "skipped": skipped }))
+        }
+        Err(broadcast::error::RecvError::Closed) => {
+            *subscription = None;
+            None
+        }
+    }
+}
+
+async fn next_alert(alerts: &mut Option<broadcast::Receiver<AlertEvent>>) -> Option<Value> {
+    let Some(receiver) = alerts else {
+        return std::future::pending().await;
+    };
+
+    match receiver.recv().await {
+        Ok(event) => Some(json!({ "type": "alert", "data": event })),
+        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+            Some(json!({ "type": "lagged", "topic": "alerts", "skipped": skipped }))
+        }
+        Err(broadcast::error::RecvError::Closed) => {
+            *alerts = None;
+            None
+        }
+    }
+}
+
+async fn handle_message(
+    state: &SharedState,
+    principal: &Principal,
+    client_ip: Option<IpAddr>,
+    text: &str,
+    subscriptions: &mut Subscriptions,
+    outbox: &mpsc::Sender<Value>,
+) -> Option<Value> {
+    let message: ClientMessage = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(err) => return Some(error(None, &format!("invalid message: {err}"))),
+    };
+
+    let reply = match message {
+        ClientMessage::Subscribe {
+            id,
+            topic: Topic::Alerts,
+            ..
+        } => {
+            subscriptions.alerts = Some(state.health().subscribe_alerts());
+            response(
+                id,
+                json!({ "subscribed": "alerts", "firing": state.health().firing_alerts() }),
+            )
+        }
+        ClientMessage::Subscribe {
+            id,
+            topic: Topic::Snapshots,
+            metrics,
+        } => match stream::parse_metric_list(&metrics.join(",")) {
+            Ok(metrics) => {
+                subscriptions.snapshots = Some(Subscription {
+                    receiver: state.subscribe(),
+                    metrics,
+                });
+                response(id, json!({ "subscribed": "snapshots" }))
+            }
+            Err(err) => error(id, &err.to_string()),
+        },
+        ClientMessage::Unsubscribe { id, topic } => {
+            if topic != Some(Topic::Alerts) {
+                subscriptions.snapshots = None;
+            }
+            if topic != Some(Topic::Snapshots) {
+                subscriptions.alerts = None;
+            }
+            response(
+                id,
+                json!({ "unsubscribed": topic.map_or("all", Topic::as_str) }),
+            )
+        }
+        ClientMessage::Latest { id } => {
+            let mut snapshot = state.latest_snapshot().await;
+            api::redact_processes(principal, [&mut snapshot]);
+            response(id, json!(snapshot))
+        }
+        ClientMessage::History {
+            id,
+            from,
+            to,
+            cursor,
+            limit,
+        } => {
+            // Charged to the same budget as the HTTP history endpoints.
+            let config = state.config();
+            let page = match state
+                .rate_limiter()
+                .check_history(client_ip, config.rate_limits())
+            {
+                Ok(()) => api::history_page(state, from, to, cursor, limit).await,
+                Err(retry_after) => Err(ApiError::TooManyRequests(retry_after)),
+            };
+            match page {
+                Ok((mut snapshots, next_cursor)) => {
+                    api::redact_processes(principal, &mut snapshots);
+                    response(
+                        id,
+                        json!({ "snapshots": snapshots, "next_cursor": next_cursor }),
+                    )
+                }
+                Err(err) => error(id, &err.to_string()),
+            }
+        }
+        ClientMessage::Collect { id } if !principal.allows(Scope::Admin) => {
+            let forbidden = ApiError::Forbidden {
+                key: principal.name.clone(),
+                scope: Scope::Admin,
+            };
+            error(id, &forbidden.to_string())
+        }
+        ClientMessage::Collect { id } => {
+            spawn_collection(state, id, outbox.clone());
+            return None;
+        }
+    };
+
+    Some(reply)
+}
+
+/// Triggers a collection and replies with the resulting snapshot once the
+/// scheduler has recorded it.
+fn spawn_collection(state: &SharedState, id: Option<Value>, outbox: mpsc::Sender<Value>) {
+    let mut receiver = state.subscribe();
+    let wait = state.config().collection_interval() + COLLECT_GRACE;
+    state.request_collection();
+
+    tokio::spawn(async move {
+        let reply = match tokio::time::timeout(wait, receiver.recv()).await {
+            Ok(Ok(snapshot)) => response(id, json!(snapshot)),
+            _ => error(id, "collection did not finish in time"),
+        };
+        let _ = outbox.send(reply).await;
+    });
+}
+
+fn response(id: Option<Value>, data: Value) -> Value {
+    json!({ "type": "response", "id": id, "data": data })
+}
+
+fn error(id: Option<Value>, message: &str) -> Value {
+    json!({ "type": "error", "id": id, "message": message })
+}